@@ -0,0 +1,16 @@
+use std::io::Result;
+
+fn main() -> Result<()> {
+    // Compile protobuf files
+    prost_build::compile_protos(
+        &[
+            "../proto/proto_demux/demux.proto",
+            "../proto/proto_ownership/ownership.proto",
+            "../proto/proto_denuvo_service/denuvo_service.proto",
+            "../proto/authfiles/remember_device_file.proto",
+            "../proto/authfiles/user_dat_file.proto",
+        ],
+        &["../proto/"],
+    )?;
+    Ok(())
+}
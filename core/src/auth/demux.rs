@@ -0,0 +1,693 @@
+use prost::Message;
+use std::{
+    collections::HashMap,
+    io::{self, Read, Write},
+    sync::mpsc::{Receiver, RecvTimeoutError, Sender, TryRecvError, channel},
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use super::transport::{self, Transport, WriteHalf};
+use crate::config::NetworkConfig;
+use crate::error::DbDataError;
+use crate::proto::demux::{
+    AuthenticateReq, ClientVersionPush, DataMessage, Downstream, GetPatchInfoReq, KeepAlivePush,
+    OpenConnectionReq, Push, Req, Rsp, Token, Upstream,
+    connection_closed_push::ConnectionErrorCode,
+};
+
+pub const DEMUX_HOST: &str = "dmx.upc.ubisoft.com";
+pub const DEMUX_PORT: u16 = 443;
+const MAX_FRAME_LEN: usize = 1024 * 1024;
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Writes one length-prefixed demux frame.
+pub fn write_frame<W: Write + ?Sized>(stream: &mut W, data: &[u8]) -> Result<(), DbDataError> {
+    let mut frame = Vec::with_capacity(4 + data.len());
+    frame.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    frame.extend_from_slice(data);
+
+    stream.write_all(&frame)?;
+    stream.flush()?;
+
+    Ok(())
+}
+
+/// Reads one length-prefixed demux frame.
+pub fn read_frame<R: Read + ?Sized>(stream: &mut R) -> Result<Vec<u8>, DbDataError> {
+    let mut len_buf = [0u8; 4];
+    stream.read_exact(&mut len_buf)?;
+    let len = u32::from_be_bytes(len_buf) as usize;
+
+    if len == 0 {
+        return Err(DbDataError::Protocol(
+            "Received zero-length message".to_string(),
+        ));
+    }
+    if len > MAX_FRAME_LEN {
+        return Err(DbDataError::Protocol(format!(
+            "Message length {} too large",
+            len
+        )));
+    }
+
+    let mut data = vec![0u8; len];
+    stream.read_exact(&mut data)?;
+
+    Ok(data)
+}
+
+type Waiter<T> = Sender<Result<T, DbDataError>>;
+type ServiceQueue = Arc<Mutex<Receiver<Result<Vec<u8>, DbDataError>>>>;
+
+/// Callers waiting on the reader thread, keyed by what will answer them.
+#[derive(Default)]
+struct Dispatch {
+    requests: HashMap<u32, Waiter<Rsp>>,
+    connections: HashMap<u32, Waiter<Vec<u8>>>,
+    subscribers: Vec<Sender<Push>>,
+    closed: bool,
+}
+
+impl Dispatch {
+    /// Fails every waiting caller with a fresh copy of `error`.
+    fn fail_all(&mut self, error: impl Fn() -> DbDataError) {
+        for (_, waiter) in self.requests.drain() {
+            let _ = waiter.send(Err(error()));
+        }
+        for (_, waiter) in self.connections.drain() {
+            let _ = waiter.send(Err(error()));
+        }
+    }
+}
+
+/// A demux connection shared by any number of callers.
+///
+/// A background thread reads every `Downstream` frame and hands responses to
+/// the caller waiting on the same `request_id`, service data to the caller
+/// waiting on the same `connection_id`, answers keep-alives and forwards any
+/// other push to [`DemuxSocket::subscribe`]rs.
+pub struct DemuxSocket {
+    writer: Arc<Mutex<Box<dyn WriteHalf>>>,
+    dispatch: Arc<Mutex<Dispatch>>,
+    connections: Mutex<HashMap<u32, ServiceQueue>>,
+    request_id: Mutex<u32>,
+    timeout: Duration,
+}
+
+impl DemuxSocket {
+    /// Connects to the demux server configured in `network`.
+    pub fn connect(network: &NetworkConfig) -> Result<Self, DbDataError> {
+        let host = &network.demux_host;
+        let port = network.demux_port;
+        log::info!("Connecting to demux server at {}:{}", host, port);
+
+        let options = network.connect_options()?;
+        let socket = if network.demux_tls {
+            Self::from_transport(transport::connect_tls(
+                host,
+                port,
+                &[&rustls::version::TLS12],
+                &options,
+            )?)?
+        } else {
+            Self::from_transport(transport::connect_tcp(host, port, &options)?)?
+        };
+
+        log::info!("Connected to demux server");
+
+        Ok(socket.with_timeout(options.read_timeout))
+    }
+
+    /// Runs the demux protocol over an already established stream and starts
+    /// the reader thread.
+    pub fn from_transport<T: Transport + 'static>(stream: T) -> Result<Self, DbDataError> {
+        let (reader, writer) = Box::new(stream).split()?;
+        let writer: Arc<Mutex<Box<dyn WriteHalf>>> = Arc::new(Mutex::new(writer));
+        let dispatch = Arc::new(Mutex::new(Dispatch::default()));
+
+        let thread_writer = writer.clone();
+        let thread_dispatch = dispatch.clone();
+        thread::Builder::new()
+            .name("demux-reader".to_string())
+            .spawn(move || read_loop(reader, thread_writer, thread_dispatch))?;
+
+        Ok(Self {
+            writer,
+            dispatch,
+            connections: Mutex::new(HashMap::new()),
+            request_id: Mutex::new(1),
+            timeout: DEFAULT_TIMEOUT,
+        })
+    }
+
+    /// How long to wait for a response before giving up.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn disconnect(&self) {
+        log::info!("Disconnecting from demux server");
+        if let Ok(mut writer) = self.writer.lock() {
+            let _ = writer.shutdown();
+        }
+    }
+
+    /// Whether the reader thread has seen the connection end.
+    pub fn is_closed(&self) -> bool {
+        self.dispatch.lock().unwrap().closed
+    }
+
+    /// Receives every push other than keep-alives and service data.
+    pub fn subscribe(&self) -> Receiver<Push> {
+        let (tx, rx) = channel();
+        self.dispatch.lock().unwrap().subscribers.push(tx);
+        rx
+    }
+
+    fn next_request_id(&self) -> u32 {
+        let mut id = self.request_id.lock().unwrap();
+        let current = *id;
+        *id += 1;
+        current
+    }
+
+    fn send_raw(&self, data: &[u8]) -> Result<(), DbDataError> {
+        let mut writer = self.writer.lock().unwrap();
+        write_frame(&mut **writer, data)
+    }
+
+    fn wait<T>(&self, rx: &Receiver<Result<T, DbDataError>>) -> Result<T, DbDataError> {
+        match rx.recv_timeout(self.timeout) {
+            Ok(result) => result,
+            Err(RecvTimeoutError::Timeout) => Err(DbDataError::ReplyTimeout),
+            Err(RecvTimeoutError::Disconnected) => Err(DbDataError::ConnectionClosed(None)),
+        }
+    }
+
+    /// Sends a request and waits for the response with the same `request_id`.
+    ///
+    /// Like service data, a request failed by a `ClientOutdatedPush` is
+    /// retried once after pushing the latest version again.
+    fn send_upstream_msg(&self, upstream: Upstream) -> Result<Downstream, DbDataError> {
+        match self.request_once(&upstream) {
+            Err(DbDataError::ClientOutdated) => {
+                log::warn!("Server reports the client as outdated, pushing the latest version");
+                self.push_version()?;
+                self.request_once(&upstream)
+            }
+            result => result,
+        }
+    }
+
+    /// [`send_upstream_msg`](Self::send_upstream_msg) without the retry,
+    /// for the patch info request `push_version` itself makes.
+    fn request_once(&self, upstream: &Upstream) -> Result<Downstream, DbDataError> {
+        let request_id = upstream
+            .request
+            .as_ref()
+            .map(|req| req.request_id)
+            .ok_or_else(|| DbDataError::Protocol("Upstream without request".to_string()))?;
+
+        let (tx, rx) = channel();
+        {
+            let mut dispatch = self.dispatch.lock().unwrap();
+            if dispatch.closed {
+                return Err(DbDataError::ConnectionClosed(None));
+            }
+            dispatch.requests.insert(request_id, tx);
+        }
+
+        let result = self
+            .send_raw(&upstream.encode_to_vec())
+            .and_then(|_| self.wait(&rx));
+        self.dispatch.lock().unwrap().requests.remove(&request_id);
+
+        Ok(Downstream {
+            response: Some(result?),
+            push: None,
+        })
+    }
+
+    /// The queue service data for `connection_id` is delivered to.
+    fn connection_queue(&self, connection_id: u32) -> ServiceQueue {
+        let mut connections = self.connections.lock().unwrap();
+
+        // Once the reader drops the sender the queue reports the connection
+        // as closed, which is what later calls on it should see.
+        if let Some(queue) = connections.get(&connection_id) {
+            return queue.clone();
+        }
+
+        let (tx, rx) = channel();
+        let mut dispatch = self.dispatch.lock().unwrap();
+        if !dispatch.closed {
+            dispatch.connections.insert(connection_id, tx);
+        }
+        let queue = Arc::new(Mutex::new(rx));
+        connections.insert(connection_id, queue.clone());
+        queue
+    }
+
+    pub fn push_version(&self) -> Result<(), DbDataError> {
+        let latest_version = self.get_latest_version()?;
+        log::info!("Pushing client version: {}", latest_version);
+
+        let upstream = Upstream {
+            request: None,
+            push: Some(Push {
+                client_version: Some(ClientVersionPush {
+                    version: latest_version,
+                }),
+                ..Default::default()
+            }),
+        };
+
+        let data = upstream.encode_to_vec();
+        self.send_raw(&data)?;
+        Ok(())
+    }
+
+    pub fn get_latest_version(&self) -> Result<u32, DbDataError> {
+        log::info!("Getting latest version from server");
+
+        let req = Req {
+            request_id: self.next_request_id(),
+            authenticate_req: None,
+            get_patch_info_req: Some(GetPatchInfoReq {
+                patch_track_id: "DEFAULT".to_string(),
+                test_config: false,
+                track_type: Some(0),
+            }),
+            service_request: None,
+            open_connection_req: None,
+            client_ip_override: None,
+        };
+
+        let upstream = Upstream {
+            request: Some(req),
+            push: None,
+        };
+
+        let downstream = self.request_once(&upstream)?;
+
+        if let Some(rsp) = downstream.response
+            && let Some(patch_rsp) = rsp.get_patch_info_rsp
+        {
+            log::info!("Latest version from server: {}", patch_rsp.latest_version);
+            return Ok(patch_rsp.latest_version);
+        }
+
+        Err(DbDataError::Protocol(
+            "Failed to get latest version".to_string(),
+        ))
+    }
+
+    pub fn send_keep_alive(&self) -> Result<(), DbDataError> {
+        let upstream = Upstream {
+            request: None,
+            push: Some(Push {
+                keep_alive: Some(KeepAlivePush {}),
+                ..Default::default()
+            }),
+        };
+        self.send_raw(&upstream.encode_to_vec())
+    }
+
+    pub fn authenticate(&self, ticket: &str, keep_alive: bool) -> Result<(), DbDataError> {
+        log::info!("Authenticating with demux server");
+
+        let req = Req {
+            request_id: self.next_request_id(),
+            authenticate_req: Some(AuthenticateReq {
+                token: Token {
+                    ubi_ticket: Some(ticket.to_string()),
+                    orbit_token: None,
+                    ubi_token: None,
+                },
+                send_keep_alive: Some(keep_alive),
+                client_id: Some("uplay_pc".to_string()),
+                logout_push_group_id: None,
+            }),
+            get_patch_info_req: None,
+            service_request: None,
+            open_connection_req: None,
+            client_ip_override: None,
+        };
+
+        let upstream = Upstream {
+            request: Some(req),
+            push: None,
+        };
+
+        let downstream = self.send_upstream_msg(upstream)?;
+
+        if let Some(rsp) = downstream.response
+            && let Some(auth_rsp) = rsp.authenticate_rsp
+        {
+            log::info!("Authentication result: {}", auth_rsp.success);
+            if !auth_rsp.success {
+                return Err(DbDataError::DemuxAuthRejected {
+                    expired: auth_rsp.expired.unwrap_or(false),
+                    banned: auth_rsp.banned.unwrap_or(false),
+                });
+            }
+            return Ok(());
+        }
+
+        Err(DbDataError::Protocol(
+            "Unexpected response to authenticate request".to_string(),
+        ))
+    }
+
+    pub fn open_connection(&self, service_name: &str) -> Result<u32, DbDataError> {
+        log::info!("Opening connection to service: {}", service_name);
+
+        let req = Req {
+            request_id: self.next_request_id(),
+            authenticate_req: None,
+            get_patch_info_req: None,
+            service_request: None,
+            open_connection_req: Some(OpenConnectionReq {
+                service_name: service_name.to_string(),
+            }),
+            client_ip_override: None,
+        };
+
+        let upstream = Upstream {
+            request: Some(req),
+            push: None,
+        };
+
+        let downstream = self.send_upstream_msg(upstream)?;
+
+        if let Some(rsp) = downstream.response
+            && let Some(conn_rsp) = rsp.open_connection_rsp
+        {
+            if conn_rsp.success {
+                log::info!("Connection opened with ID: {}", conn_rsp.connection_id);
+                // The server may reuse the id of a connection it closed.
+                self.connections
+                    .lock()
+                    .unwrap()
+                    .remove(&conn_rsp.connection_id);
+                self.connection_queue(conn_rsp.connection_id);
+                return Ok(conn_rsp.connection_id);
+            } else {
+                return Err(DbDataError::ServiceUnavailable(service_name.to_string()));
+            }
+        }
+
+        Err(DbDataError::Protocol(
+            "Unexpected response to open connection request".to_string(),
+        ))
+    }
+
+    /// Sends `data` on a service connection and waits for the next message
+    /// the service sends back on it. Calls on the same connection are
+    /// serialized; calls on different connections may overlap.
+    ///
+    /// If the server reports the client as outdated, the latest version is
+    /// pushed again and the request retried once.
+    pub fn send_service_data(
+        &self,
+        connection_id: u32,
+        data: &[u8],
+    ) -> Result<Vec<u8>, DbDataError> {
+        let mut prefixed_data = Vec::with_capacity(4 + data.len());
+        prefixed_data.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        prefixed_data.extend_from_slice(data);
+
+        let upstream = Upstream {
+            request: None,
+            push: Some(Push {
+                data: Some(DataMessage {
+                    connection_id,
+                    data: prefixed_data,
+                }),
+                ..Default::default()
+            }),
+        };
+        let send_data = upstream.encode_to_vec();
+
+        match self.exchange(connection_id, &send_data) {
+            Err(DbDataError::ClientOutdated) => {
+                log::warn!("Server reports the client as outdated, pushing the latest version");
+                self.push_version()?;
+                self.exchange(connection_id, &send_data)
+            }
+            result => result,
+        }
+    }
+
+    fn exchange(&self, connection_id: u32, send_data: &[u8]) -> Result<Vec<u8>, DbDataError> {
+        let queue = self.connection_queue(connection_id);
+        let queue = queue.lock().unwrap();
+
+        // Anything already queued answers an earlier call that gave up.
+        loop {
+            match queue.try_recv() {
+                Ok(Ok(_)) | Ok(Err(DbDataError::ClientOutdated)) => {
+                    log::debug!("Dropping stale data for connection {}", connection_id);
+                }
+                Ok(Err(e)) => return Err(e),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return Err(DbDataError::ConnectionClosed(None)),
+            }
+        }
+
+        self.send_raw(send_data)?;
+        unprefix(self.wait(&queue)?)
+    }
+
+    /// Waits for the next message on a service connection without sending
+    /// anything, e.g. when the last one was a push rather than the answer.
+    pub fn receive_service_data(&self, connection_id: u32) -> Result<Vec<u8>, DbDataError> {
+        let queue = self.connection_queue(connection_id);
+        let queue = queue.lock().unwrap();
+        unprefix(self.wait(&queue)?)
+    }
+}
+
+/// Strips the length prefix of a service message.
+fn unprefix(raw_data: Vec<u8>) -> Result<Vec<u8>, DbDataError> {
+    if raw_data.len() < 4 {
+        return Err(DbDataError::Protocol(
+            "Service response too short".to_string(),
+        ));
+    }
+    let len = u32::from_be_bytes([raw_data[0], raw_data[1], raw_data[2], raw_data[3]]) as usize;
+    if raw_data.len() < 4 + len {
+        return Err(DbDataError::Protocol(format!(
+            "Service response truncated: expected {} bytes, got {}",
+            len,
+            raw_data.len() - 4
+        )));
+    }
+    Ok(raw_data[4..4 + len].to_vec())
+}
+
+/// Anything service connections can be opened and used on: a single
+/// [`DemuxSocket`] or a reconnecting [`DemuxSession`](super::DemuxSession).
+pub trait ServiceLink: Sync {
+    fn open_connection(&self, service_name: &str) -> Result<u32, DbDataError>;
+
+    fn send_service_data(&self, connection_id: u32, data: &[u8]) -> Result<Vec<u8>, DbDataError>;
+
+    fn receive_service_data(&self, connection_id: u32) -> Result<Vec<u8>, DbDataError>;
+}
+
+impl ServiceLink for DemuxSocket {
+    fn open_connection(&self, service_name: &str) -> Result<u32, DbDataError> {
+        DemuxSocket::open_connection(self, service_name)
+    }
+
+    fn send_service_data(&self, connection_id: u32, data: &[u8]) -> Result<Vec<u8>, DbDataError> {
+        DemuxSocket::send_service_data(self, connection_id, data)
+    }
+
+    fn receive_service_data(&self, connection_id: u32) -> Result<Vec<u8>, DbDataError> {
+        DemuxSocket::receive_service_data(self, connection_id)
+    }
+}
+
+impl Drop for DemuxSocket {
+    fn drop(&mut self) {
+        // Ends the reader thread along with the connection.
+        if let Ok(mut writer) = self.writer.lock() {
+            let _ = writer.shutdown();
+        }
+    }
+}
+
+/// Splits a byte stream into frames without losing partial frames when a
+/// read times out.
+struct FrameReader {
+    inner: Box<dyn Read + Send>,
+    buf: Vec<u8>,
+}
+
+impl FrameReader {
+    /// Returns the next frame, or `None` at end of stream.
+    fn next_frame(&mut self) -> Result<Option<Vec<u8>>, DbDataError> {
+        loop {
+            if self.buf.len() >= 4 {
+                let len = u32::from_be_bytes([self.buf[0], self.buf[1], self.buf[2], self.buf[3]])
+                    as usize;
+                if len == 0 {
+                    return Err(DbDataError::Protocol(
+                        "Received zero-length message".to_string(),
+                    ));
+                }
+                if len > MAX_FRAME_LEN {
+                    return Err(DbDataError::Protocol(format!(
+                        "Message length {} too large",
+                        len
+                    )));
+                }
+                if self.buf.len() >= 4 + len {
+                    let frame = self.buf[4..4 + len].to_vec();
+                    self.buf.drain(..4 + len);
+                    return Ok(Some(frame));
+                }
+            }
+
+            let mut chunk = [0u8; 16 * 1024];
+            let n = self.inner.read(&mut chunk)?;
+            if n == 0 {
+                return Ok(None);
+            }
+            self.buf.extend_from_slice(&chunk[..n]);
+        }
+    }
+}
+
+fn read_loop(
+    reader: Box<dyn Read + Send>,
+    writer: Arc<Mutex<Box<dyn WriteHalf>>>,
+    dispatch: Arc<Mutex<Dispatch>>,
+) {
+    let mut reader = FrameReader {
+        inner: reader,
+        buf: Vec::new(),
+    };
+
+    loop {
+        let frame = match reader.next_frame() {
+            Ok(Some(frame)) => frame,
+            Ok(None) => {
+                log::info!("Demux server closed the connection");
+                break;
+            }
+            // An idle connection hits the socket read timeout; keep waiting.
+            Err(DbDataError::Io(e))
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock
+                        | io::ErrorKind::TimedOut
+                        | io::ErrorKind::Interrupted
+                ) =>
+            {
+                continue;
+            }
+            Err(e) => {
+                log::warn!("Demux read failed: {}", e);
+                break;
+            }
+        };
+
+        match Downstream::decode(frame.as_slice()) {
+            Ok(downstream) => dispatch_downstream(downstream, &writer, &dispatch),
+            Err(e) => log::warn!("Ignoring undecodable demux frame: {}", e),
+        }
+    }
+
+    let mut dispatch = dispatch.lock().unwrap();
+    dispatch.closed = true;
+    dispatch.fail_all(|| DbDataError::ConnectionClosed(None));
+    dispatch.subscribers.clear();
+}
+
+fn dispatch_downstream(
+    downstream: Downstream,
+    writer: &Mutex<Box<dyn WriteHalf>>,
+    dispatch: &Mutex<Dispatch>,
+) {
+    if let Some(rsp) = downstream.response {
+        match dispatch.lock().unwrap().requests.remove(&rsp.request_id) {
+            Some(waiter) => {
+                let _ = waiter.send(Ok(rsp));
+            }
+            None => log::debug!("Dropping response to unknown request {}", rsp.request_id),
+        }
+    }
+
+    let Some(push) = downstream.push else {
+        return;
+    };
+
+    if push.keep_alive.is_some() {
+        log::debug!("Received keep-alive, responding...");
+        let upstream = Upstream {
+            request: None,
+            push: Some(Push {
+                keep_alive: Some(KeepAlivePush {}),
+                ..Default::default()
+            }),
+        };
+        let mut writer = writer.lock().unwrap();
+        if let Err(e) = write_frame(&mut **writer, &upstream.encode_to_vec()) {
+            log::warn!("Failed to answer keep-alive: {}", e);
+        }
+        return;
+    }
+
+    let mut dispatch = dispatch.lock().unwrap();
+
+    if let Some(data_msg) = push.data {
+        match dispatch.connections.get(&data_msg.connection_id) {
+            Some(waiter) => {
+                let _ = waiter.send(Ok(data_msg.data));
+            }
+            None => log::debug!(
+                "Dropping data for unknown connection {}",
+                data_msg.connection_id
+            ),
+        }
+        return;
+    }
+
+    if let Some(ref closed) = push.connection_closed {
+        let code = closed
+            .error_code
+            .and_then(|code| ConnectionErrorCode::try_from(code).ok());
+        match dispatch.connections.remove(&closed.connection_id) {
+            Some(waiter) => {
+                let _ = waiter.send(Err(DbDataError::ConnectionClosed(code)));
+            }
+            // Not one of our service connections: the demux connection
+            // itself is going away.
+            None => {
+                dispatch.closed = true;
+                dispatch.fail_all(|| DbDataError::ConnectionClosed(code));
+            }
+        }
+    }
+
+    if push.client_outdated.is_some() {
+        // The connections stay usable once the version is pushed again, so
+        // only the callers waiting on them are told.
+        for (_, waiter) in dispatch.requests.drain() {
+            let _ = waiter.send(Err(DbDataError::ClientOutdated));
+        }
+        for waiter in dispatch.connections.values() {
+            let _ = waiter.send(Err(DbDataError::ClientOutdated));
+        }
+    }
+
+    dispatch
+        .subscribers
+        .retain(|tx| tx.send(push.clone()).is_ok());
+}
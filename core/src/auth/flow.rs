@@ -0,0 +1,534 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::error::DbDataError;
+
+use super::{
+    DemuxSession, DemuxSocket, LogProgress, LoginCredentials, LoginEndpoint, ProgressSink,
+    ServiceLink, Stage, TwoFactorPrompt, delete_session, login_with_prompt, login_with_remember_me,
+    refresh_session, run_stage,
+};
+use crate::config::DbDataConfig;
+use crate::http::HttpClient;
+use crate::proto::ownership::{OwnedGame, owned_game::DenuvoActivationOverwrite};
+use crate::services::{DenuvoConnection, OwnershipConnection};
+use crate::time;
+
+/// Result of the authentication flow
+#[derive(Debug, Clone)]
+pub struct AuthResult {
+    pub game_token: String,
+    pub ownership_token: Option<String>,
+    pub owned_dlcs: Vec<u32>,
+    /// When the tokens were handed out, by the server's clock.
+    pub issued_at: SystemTime,
+    /// When the ownership token expires, by the server's clock.
+    pub expires_at: Option<SystemTime>,
+    /// Seconds the server's clock is ahead of ours.
+    pub clock_skew: i64,
+    /// Whether the app is held as a time trial, so `game_token` is a time
+    /// token that expires with the trial and has to be fetched again.
+    pub time_trial: bool,
+}
+
+/// How long before the ticket expires [`TokenClient::fetch`] refreshes it.
+pub const REFRESH_MARGIN: Duration = Duration::from_secs(10 * 60);
+
+pub fn authenticate_and_get_tokens(
+    config: &DbDataConfig,
+    prompt: &dyn TwoFactorPrompt,
+    request_token: &str,
+    dlcs: Vec<u32>,
+) -> Result<AuthResult, DbDataError> {
+    let client = TokenClient::connect(config, prompt)?;
+    let result = client.fetch(request_token, dlcs);
+    client.close();
+
+    result
+}
+
+/// A login and demux session kept open between token requests, so a later
+/// refresh does not have to log in again.
+///
+/// The ubiservices session is refreshed when it is about to expire and
+/// deleted when the client is closed or dropped.
+pub struct TokenClient {
+    app_id: u32,
+    http: HttpClient,
+    endpoint: LoginEndpoint,
+    credentials: Mutex<LoginCredentials>,
+    session: DemuxSession,
+    progress: Arc<dyn ProgressSink>,
+    closed: AtomicBool,
+}
+
+impl TokenClient {
+    /// Logs in over HTTP and starts a demux session. `prompt` is asked for
+    /// a code if the account has two-factor authentication enabled.
+    pub fn connect(
+        config: &DbDataConfig,
+        prompt: &dyn TwoFactorPrompt,
+    ) -> Result<Self, DbDataError> {
+        Self::connect_with_progress(config, prompt, Arc::new(LogProgress))
+    }
+
+    /// Like [`connect`](Self::connect), telling `progress` about every
+    /// stage of this and later fetches.
+    pub fn connect_with_progress(
+        config: &DbDataConfig,
+        prompt: &dyn TwoFactorPrompt,
+        progress: Arc<dyn ProgressSink>,
+    ) -> Result<Self, DbDataError> {
+        log::info!("Starting authentication flow for app: {}", config.app_id);
+
+        let network = &config.network;
+        let http = HttpClient::new(network.connect_options()?);
+        let endpoint = network.login_endpoint();
+        let credentials = run_stage(&*progress, Stage::Login, || log_in(&http, config, prompt))?;
+        log::info!("HTTP login successful");
+
+        let session = match run_stage(&*progress, Stage::DemuxConnect, || {
+            DemuxSession::connect(network, &credentials.ticket)
+        }) {
+            Ok(session) => session,
+            Err(e) => {
+                log_out(&http, &endpoint, &credentials);
+                return Err(e);
+            }
+        };
+        log::info!("Demux authentication successful");
+
+        Ok(Self {
+            app_id: config.app_id,
+            http,
+            endpoint,
+            credentials: Mutex::new(credentials),
+            session,
+            progress,
+            closed: AtomicBool::new(false),
+        })
+    }
+
+    pub fn fetch(&self, request_token: &str, dlcs: Vec<u32>) -> Result<AuthResult, DbDataError> {
+        self.fetch_app(self.app_id, request_token, dlcs)
+    }
+
+    /// Like [`fetch`](Self::fetch), for another app on the same account,
+    /// such as a second executable with its own product id.
+    pub fn fetch_app(
+        &self,
+        app_id: u32,
+        request_token: &str,
+        dlcs: Vec<u32>,
+    ) -> Result<AuthResult, DbDataError> {
+        self.flow(app_id, request_token, dlcs)?.run()
+    }
+
+    /// The demux side of [`fetch_app`](Self::fetch_app), to run stage by
+    /// stage or retry after a failure.
+    pub fn flow(
+        &self,
+        app_id: u32,
+        request_token: &str,
+        dlcs: Vec<u32>,
+    ) -> Result<TokenFlow<'_>, DbDataError> {
+        self.refresh_if_expiring()?;
+
+        Ok(TokenFlow::new(
+            &self.session,
+            &self.credentials(),
+            app_id,
+            request_token,
+            dlcs,
+        )
+        .with_progress(&*self.progress))
+    }
+
+    /// Refreshes the ticket if it expires within [`REFRESH_MARGIN`]. A
+    /// failed refresh only matters once the ticket has actually expired.
+    fn refresh_if_expiring(&self) -> Result<(), DbDataError> {
+        let mut credentials = self.credentials.lock().unwrap();
+        if !credentials.expires_within(REFRESH_MARGIN) {
+            return Ok(());
+        }
+
+        match refresh_session(&self.http, &self.endpoint, &credentials) {
+            Ok(refreshed) => {
+                self.session.set_ticket(&refreshed.ticket);
+                *credentials = refreshed;
+                Ok(())
+            }
+            Err(e) if !credentials.expires_within(Duration::ZERO) => {
+                log::warn!("Session refresh failed, keeping the current ticket: {}", e);
+                Ok(())
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// The ticket to save for the next launch, if the login handed one out.
+    pub fn remember_me_ticket(&self) -> Option<String> {
+        self.credentials().remember_me_ticket
+    }
+
+    /// The ticket that lets this machine skip two-factor authentication,
+    /// handed out after a code was entered.
+    pub fn remember_device_ticket(&self) -> Option<String> {
+        self.credentials().remember_device_ticket
+    }
+
+    pub fn credentials(&self) -> LoginCredentials {
+        self.credentials.lock().unwrap().clone()
+    }
+
+    /// Disconnects from demux and deletes the ubiservices session.
+    pub fn close(&self) {
+        if self.closed.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        self.session.close();
+        log_out(&self.http, &self.endpoint, &self.credentials());
+    }
+}
+
+impl Drop for TokenClient {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+fn log_out(http: &HttpClient, endpoint: &LoginEndpoint, credentials: &LoginCredentials) {
+    if let Err(e) = delete_session(http, endpoint, credentials) {
+        log::warn!("Failed to delete ubiservices session: {}", e);
+    }
+}
+
+/// Logs in with the saved remember-me ticket, falling back to the email and
+/// password when there is no ticket or it was rejected.
+fn log_in(
+    client: &HttpClient,
+    config: &DbDataConfig,
+    prompt: &dyn TwoFactorPrompt,
+) -> Result<LoginCredentials, DbDataError> {
+    let endpoint = config.network.login_endpoint();
+
+    if let Some(ticket) = &config.remember_me_ticket {
+        match login_with_remember_me(
+            client,
+            &endpoint,
+            ticket,
+            config.remember_device_ticket.as_deref(),
+            prompt,
+        ) {
+            Err(DbDataError::Login {
+                status: 400 | 401, ..
+            }) if config.has_password() => {
+                log::warn!("Remember-me ticket rejected, logging in with the password");
+            }
+            Err(DbDataError::Login {
+                status: 400 | 401, ..
+            }) => return Err(DbDataError::RememberMeExpired),
+            result => return result,
+        }
+    }
+
+    login_with_prompt(
+        client,
+        &endpoint,
+        &config.email,
+        &config.password,
+        config.remember_device_ticket.as_deref(),
+        prompt,
+    )
+}
+
+/// Runs the demux part of the flow on an already connected socket: pushes the
+/// client version, authenticates and asks the ownership and denuvo services
+/// for tokens.
+pub fn fetch_tokens(
+    socket: &DemuxSocket,
+    credentials: &LoginCredentials,
+    app_id: u32,
+    request_token: &str,
+    dlcs: Vec<u32>,
+) -> Result<AuthResult, DbDataError> {
+    run_stage(&LogProgress, Stage::PushVersion, || socket.push_version())?;
+
+    run_stage(&LogProgress, Stage::Authenticate, || {
+        socket.authenticate(&credentials.ticket, true)
+    })?;
+    log::info!("Demux authentication successful");
+
+    request_tokens(socket, credentials, app_id, request_token, dlcs)
+}
+
+/// Asks the ownership and denuvo services for tokens over an authenticated
+/// link.
+pub fn request_tokens(
+    link: &dyn ServiceLink,
+    credentials: &LoginCredentials,
+    app_id: u32,
+    request_token: &str,
+    dlcs: Vec<u32>,
+) -> Result<AuthResult, DbDataError> {
+    TokenFlow::new(link, credentials, app_id, request_token, dlcs).run()
+}
+
+/// The ownership and denuvo requests for one app, run one [`Stage`] at a
+/// time.
+///
+/// What each stage gets back is kept, so a caller that holds on to the flow
+/// after a failure can call [`run`](Self::run) or [`step`](Self::step) again
+/// to pick up at the stage that failed, e.g. to retry a denuvo timeout
+/// without asking for ownership again. Nothing is retried on its own.
+pub struct TokenFlow<'a> {
+    link: &'a dyn ServiceLink,
+    progress: &'a dyn ProgressSink,
+    credentials: LoginCredentials,
+    app_id: u32,
+    request_token: String,
+    dlcs: Vec<u32>,
+    ownership: Option<OwnershipConnection<'a>>,
+    denuvo: Option<DenuvoConnection<'a>>,
+    owned_dlcs: Option<Vec<u32>>,
+    time_trial: bool,
+    issued_at: Option<SystemTime>,
+    ownership_token: Option<(String, u64)>,
+    game_token: Option<String>,
+    time_token_ttl: Option<u32>,
+    ownership_list_token: Option<Option<String>>,
+}
+
+impl<'a> TokenFlow<'a> {
+    pub fn new(
+        link: &'a dyn ServiceLink,
+        credentials: &LoginCredentials,
+        app_id: u32,
+        request_token: &str,
+        dlcs: Vec<u32>,
+    ) -> Self {
+        Self {
+            link,
+            progress: &LogProgress,
+            credentials: credentials.clone(),
+            app_id,
+            request_token: request_token.to_string(),
+            dlcs,
+            ownership: None,
+            denuvo: None,
+            owned_dlcs: None,
+            time_trial: false,
+            issued_at: None,
+            ownership_token: None,
+            game_token: None,
+            time_token_ttl: None,
+            ownership_list_token: None,
+        }
+    }
+
+    pub fn with_progress(mut self, progress: &'a dyn ProgressSink) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// The stage [`step`](Self::step) runs next, or `None` once every stage
+    /// has finished.
+    pub fn stage(&self) -> Option<Stage> {
+        if self.owned_dlcs.is_none() {
+            Some(Stage::OwnershipInitialize)
+        } else if self.ownership_token.is_none() {
+            Some(Stage::OwnershipToken)
+        } else if self.game_token.is_none() {
+            Some(Stage::DenuvoToken)
+        } else if self.ownership_list_token.is_none() {
+            Some(Stage::OwnershipListToken)
+        } else {
+            None
+        }
+    }
+
+    /// Runs the remaining stages and returns the tokens.
+    pub fn run(&mut self) -> Result<AuthResult, DbDataError> {
+        while self.step()?.is_some() {}
+        self.result()
+    }
+
+    /// Runs the next stage and returns it, or `None` when there is nothing
+    /// left to run. A failed stage stays next.
+    pub fn step(&mut self) -> Result<Option<Stage>, DbDataError> {
+        let Some(stage) = self.stage() else {
+            return Ok(None);
+        };
+        let progress = self.progress;
+
+        match stage {
+            Stage::OwnershipInitialize => {
+                let (owned_dlcs, time_trial) = run_stage(progress, stage, || {
+                    let owned_games = self.ownership()?.get_owned_games()?;
+                    self.check_ownership(&owned_games)
+                })?;
+                self.owned_dlcs = Some(owned_dlcs);
+                self.time_trial = time_trial;
+            }
+            Stage::OwnershipToken => {
+                let clock_skew = self.credentials.clock_skew.unwrap_or(0);
+                let now = SystemTime::now();
+                let issued_at = time::apply_skew(now, clock_skew).unwrap_or(now);
+                let app_id = self.app_id;
+                let token = run_stage(progress, stage, || {
+                    self.ownership()?.get_ownership_token(app_id)
+                })?;
+                self.issued_at = Some(issued_at);
+                self.ownership_token = Some(token);
+            }
+            Stage::DenuvoToken => {
+                let ownership_token = self.ownership_token.clone().unwrap_or_default().0;
+                let request_token = self.request_token.clone();
+                let time_trial = self.time_trial;
+                let (game_token, ttl) = run_stage(progress, stage, || {
+                    let denuvo = self.denuvo()?;
+                    if time_trial {
+                        denuvo
+                            .get_game_time_token(&ownership_token, &request_token)
+                            .map(|(token, ttl)| (token, Some(ttl)))
+                    } else {
+                        denuvo
+                            .get_game_token(&ownership_token, &request_token)
+                            .map(|token| (token, None))
+                    }
+                })?;
+                log::info!("Got game token");
+                self.game_token = Some(game_token);
+                self.time_token_ttl = ttl;
+            }
+            Stage::OwnershipListToken => {
+                let owned_dlcs = self.owned_dlcs.clone().unwrap_or_default();
+                let dlcs_to_validate = if self.dlcs.is_empty() {
+                    owned_dlcs
+                } else {
+                    self.dlcs.clone()
+                };
+
+                let token = if dlcs_to_validate.is_empty() {
+                    None
+                } else {
+                    let app_id = self.app_id;
+                    let game_token = self.game_token.clone().unwrap_or_default();
+                    match run_stage(progress, stage, || {
+                        self.denuvo()?.get_ownership_list_token(
+                            app_id,
+                            &game_token,
+                            dlcs_to_validate,
+                        )
+                    }) {
+                        Ok(token) => {
+                            log::info!("Got ownership list token");
+                            Some(token)
+                        }
+                        Err(e) => {
+                            log::warn!("Failed to get ownership list token: {}", e);
+                            None
+                        }
+                    }
+                };
+                self.ownership_list_token = Some(token);
+            }
+            _ => unreachable!("{:?} is not a token stage", stage),
+        }
+
+        Ok(Some(stage))
+    }
+
+    fn ownership(&mut self) -> Result<&mut OwnershipConnection<'a>, DbDataError> {
+        if self.ownership.is_none() {
+            self.ownership = Some(OwnershipConnection::new(
+                self.link,
+                self.credentials.ticket.clone(),
+                self.credentials.session_id.clone(),
+            )?);
+        }
+        Ok(self.ownership.as_mut().unwrap())
+    }
+
+    fn denuvo(&mut self) -> Result<&mut DenuvoConnection<'a>, DbDataError> {
+        if self.denuvo.is_none() {
+            self.denuvo = Some(DenuvoConnection::new(self.link)?);
+        }
+        Ok(self.denuvo.as_mut().unwrap())
+    }
+
+    /// Checks the app is owned and returns the DLCs owned with it, and
+    /// whether it is held as a time trial.
+    fn check_ownership(&self, owned_games: &[OwnedGame]) -> Result<(Vec<u32>, bool), DbDataError> {
+        let app_id = self.app_id;
+        let Some(our_app) = owned_games.iter().find(|g| g.product_id == app_id) else {
+            return Err(DbDataError::NotOwned(Some(app_id)));
+        };
+        log::info!("Ownership verified for app: {}", app_id);
+
+        let owned_dlcs: Vec<u32> = owned_games
+            .iter()
+            .filter(|g| {
+                g.owned.unwrap_or(false) && our_app.product_associations.contains(&g.product_id)
+            })
+            .map(|g| g.product_id)
+            .collect();
+        log::info!("Found {} owned DLC associations", owned_dlcs.len());
+
+        let time_trial =
+            our_app.denuvo_activation_overwrite() == DenuvoActivationOverwrite::TimeTrial;
+        if time_trial {
+            log::info!("App {} is held as a time trial", app_id);
+        }
+
+        Ok((owned_dlcs, time_trial))
+    }
+
+    fn result(&self) -> Result<AuthResult, DbDataError> {
+        let (
+            Some(owned_dlcs),
+            Some(issued_at),
+            Some((_, expiration)),
+            Some(game_token),
+            Some(ownership_token),
+        ) = (
+            &self.owned_dlcs,
+            self.issued_at,
+            &self.ownership_token,
+            &self.game_token,
+            &self.ownership_list_token,
+        )
+        else {
+            return Err(DbDataError::Protocol(format!(
+                "Token flow stopped before {:?}",
+                self.stage()
+            )));
+        };
+
+        // A time token runs out after its TTL, usually well before the
+        // ownership token does. Times too far out to represent count as no
+        // expiry.
+        let ownership_expires_at = (*expiration != 0)
+            .then(|| UNIX_EPOCH.checked_add(Duration::from_secs(*expiration)))
+            .flatten();
+        let time_token_expires_at = self
+            .time_token_ttl
+            .filter(|ttl| *ttl != 0)
+            .and_then(|ttl| issued_at.checked_add(Duration::from_secs(ttl.into())));
+
+        Ok(AuthResult {
+            game_token: game_token.clone(),
+            ownership_token: ownership_token.clone(),
+            owned_dlcs: owned_dlcs.clone(),
+            issued_at,
+            expires_at: ownership_expires_at
+                .into_iter()
+                .chain(time_token_expires_at)
+                .min(),
+            clock_skew: self.credentials.clock_skew.unwrap_or(0),
+            time_trial: self.time_trial,
+        })
+    }
+}
@@ -0,0 +1,455 @@
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::error::DbDataError;
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use serde::{Deserialize, Serialize};
+
+use super::transport::ConnectOptions;
+use super::{TwoFactorChallenge, TwoFactorPrompt};
+use crate::http::{HttpClient, Request, Response, Url};
+use crate::remember_device::sha256_hex;
+use crate::time;
+
+pub const LOGIN_HOST: &str = "public-ubiservices.ubi.com";
+pub const LOGIN_PATH: &str = "/v3/profiles/sessions";
+const APP_ID: &str = "f68a4bb5-608a-4ff2-8123-be8ef797e0a6";
+const USER_AGENT: &str = "Massgate";
+/// Codes the user may enter before the login gives up.
+const MAX_CODE_ATTEMPTS: u32 = 3;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[allow(dead_code)]
+pub struct LoginResponse {
+    pub ticket: Option<String>,
+    pub session_id: Option<String>,
+    pub user_id: Option<String>,
+    pub profile_id: Option<String>,
+    pub name_on_platform: Option<String>,
+    pub remember_me_ticket: Option<String>,
+    pub two_factor_authentication_ticket: Option<String>,
+    pub code_generation_preference: Option<Vec<String>>,
+    pub remember_device_ticket: Option<String>,
+    pub expiration: Option<String>,
+    pub server_time: Option<String>,
+}
+
+/// Body of a ubiservices error response.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ErrorResponse {
+    error_code: Option<i64>,
+    message: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct LoginRequest {
+    remember_me: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    trusted_device: Option<TrustedDevice>,
+}
+
+/// The machine registered as trusted when a two-factor code is sent.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrustedDevice {
+    pub id: String,
+    pub name: String,
+    pub friendly_name: String,
+}
+
+impl TrustedDevice {
+    /// Identifies this machine by its host name, so the id stays the same
+    /// across launches.
+    pub fn this_machine() -> Self {
+        let name = std::env::var("COMPUTERNAME")
+            .or_else(|_| std::env::var("HOSTNAME"))
+            .unwrap_or_else(|_| "dbdata".to_string());
+        let hash = sha256_hex(format!("dbdata-device:{}", name).as_bytes());
+
+        Self {
+            id: format!(
+                "{}-{}-{}-{}-{}",
+                &hash[0..8],
+                &hash[8..12],
+                &hash[12..16],
+                &hash[16..20],
+                &hash[20..32]
+            ),
+            friendly_name: name.clone(),
+            name,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LoginCredentials {
+    pub ticket: String,
+    pub session_id: String,
+    pub user_id: Option<String>,
+    /// Lets a later launch create a session without the password.
+    pub remember_me_ticket: Option<String>,
+    /// Lets later logins on this machine skip two-factor authentication.
+    pub remember_device_ticket: Option<String>,
+    /// When the ticket stops being accepted, if the server said.
+    pub expires_at: Option<Instant>,
+    /// Seconds the server's clock is ahead of ours, from the `Date` header
+    /// of the reply that created the session.
+    pub clock_skew: Option<i64>,
+}
+
+impl LoginCredentials {
+    /// Whether the ticket expires within `margin` from now.
+    pub fn expires_within(&self, margin: Duration) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| expires_at <= Instant::now() + margin)
+    }
+}
+
+/// Where the ubiservices session endpoint lives.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoginEndpoint {
+    pub host: String,
+    pub port: u16,
+    pub path: String,
+    /// Plain HTTP is only meant for local stand-ins.
+    pub tls: bool,
+}
+
+impl Default for LoginEndpoint {
+    fn default() -> Self {
+        Self {
+            host: LOGIN_HOST.to_string(),
+            port: 443,
+            path: LOGIN_PATH.to_string(),
+            tls: true,
+        }
+    }
+}
+
+impl LoginEndpoint {
+    pub fn url(&self) -> Url {
+        Url {
+            tls: self.tls,
+            host: self.host.clone(),
+            port: self.port,
+            path: self.path.clone(),
+        }
+    }
+}
+
+/// Logs in against the production ubiservices endpoint.
+pub fn login(email: &str, password: &str) -> Result<LoginCredentials, DbDataError> {
+    login_at(
+        &HttpClient::new(ConnectOptions::default()),
+        &LoginEndpoint::default(),
+        email,
+        password,
+    )
+}
+
+pub fn login_at(
+    client: &HttpClient,
+    endpoint: &LoginEndpoint,
+    email: &str,
+    password: &str,
+) -> Result<LoginCredentials, DbDataError> {
+    log::info!("Attempting login for email: {}", email);
+
+    let request = SessionRequest::new(basic_authorization(email, password));
+    match create_session(client, endpoint, &request)? {
+        SessionReply::Created(credentials) => Ok(credentials),
+        SessionReply::TwoFactor { ticket, .. } => Err(DbDataError::TwoFactorRequired { ticket }),
+    }
+}
+
+/// Logs in like [`login_at`], asking `prompt` for a code when the account
+/// has two-factor authentication enabled. A `remember_device_ticket` from an
+/// earlier two-factor login lets the server skip the prompt.
+pub fn login_with_prompt(
+    client: &HttpClient,
+    endpoint: &LoginEndpoint,
+    email: &str,
+    password: &str,
+    remember_device_ticket: Option<&str>,
+    prompt: &dyn TwoFactorPrompt,
+) -> Result<LoginCredentials, DbDataError> {
+    log::info!("Attempting login for email: {}", email);
+
+    let request = SessionRequest {
+        remember_device_ticket,
+        ..SessionRequest::new(basic_authorization(email, password))
+    };
+    login_authorized(client, endpoint, &request, prompt)
+}
+
+/// Creates a session from a remember-me ticket saved by an earlier login.
+pub fn login_with_remember_me(
+    client: &HttpClient,
+    endpoint: &LoginEndpoint,
+    remember_me_ticket: &str,
+    remember_device_ticket: Option<&str>,
+    prompt: &dyn TwoFactorPrompt,
+) -> Result<LoginCredentials, DbDataError> {
+    log::info!("Attempting login with saved remember-me ticket");
+
+    let request = SessionRequest {
+        remember_device_ticket,
+        ..SessionRequest::new(format!("rm_v1 t={}", remember_me_ticket))
+    };
+    login_authorized(client, endpoint, &request, prompt)
+}
+
+fn login_authorized(
+    client: &HttpClient,
+    endpoint: &LoginEndpoint,
+    request: &SessionRequest,
+    prompt: &dyn TwoFactorPrompt,
+) -> Result<LoginCredentials, DbDataError> {
+    let (ticket, methods) = match create_session(client, endpoint, request)? {
+        SessionReply::Created(credentials) => return Ok(credentials),
+        SessionReply::TwoFactor { ticket, methods } => (ticket, methods),
+    };
+    log::info!("Two-factor authentication required, methods: {:?}", methods);
+
+    let mut challenge = TwoFactorChallenge {
+        methods,
+        attempt: 1,
+    };
+    loop {
+        let Some(code) = prompt.code(&challenge) else {
+            log::info!("Two-factor prompt was cancelled");
+            return Err(DbDataError::TwoFactorRequired { ticket });
+        };
+
+        match complete_two_factor(client, endpoint, &ticket, code.trim()) {
+            Err(DbDataError::Login {
+                status: 400 | 401, ..
+            }) => {
+                log::warn!("Two-factor code rejected (attempt {})", challenge.attempt);
+                if challenge.attempt == MAX_CODE_ATTEMPTS {
+                    return Err(DbDataError::TwoFactorCodeRejected);
+                }
+                challenge.attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Finishes a login that answered with a two-factor ticket by sending the
+/// code the user entered. This machine is registered as trusted, so the
+/// credentials carry a remember-device ticket.
+pub fn complete_two_factor(
+    client: &HttpClient,
+    endpoint: &LoginEndpoint,
+    ticket: &str,
+    code: &str,
+) -> Result<LoginCredentials, DbDataError> {
+    let request = SessionRequest {
+        two_factor_code: Some(code),
+        trusted_device: Some(TrustedDevice::this_machine()),
+        ..SessionRequest::new(format!("ubi_2fa_v1 t={}", ticket))
+    };
+
+    match create_session(client, endpoint, &request)? {
+        SessionReply::Created(credentials) => Ok(credentials),
+        SessionReply::TwoFactor { .. } => Err(DbDataError::Protocol(
+            "Login still requires two-factor authentication after sending the code".to_string(),
+        )),
+    }
+}
+
+fn basic_authorization(email: &str, password: &str) -> String {
+    let credentials = format!("{}:{}", email, password);
+    format!("Basic {}", BASE64.encode(credentials.as_bytes()))
+}
+
+/// How a session is requested: the authorization plus optional two-factor
+/// and trusted device details.
+struct SessionRequest<'a> {
+    authorization: String,
+    two_factor_code: Option<&'a str>,
+    remember_device_ticket: Option<&'a str>,
+    trusted_device: Option<TrustedDevice>,
+}
+
+impl SessionRequest<'_> {
+    fn new(authorization: String) -> Self {
+        Self {
+            authorization,
+            two_factor_code: None,
+            remember_device_ticket: None,
+            trusted_device: None,
+        }
+    }
+}
+
+/// What a session request ended in.
+enum SessionReply {
+    Created(LoginCredentials),
+    /// The account wants a second factor before handing out a ticket.
+    TwoFactor {
+        ticket: String,
+        methods: Vec<String>,
+    },
+}
+
+fn create_session(
+    client: &HttpClient,
+    endpoint: &LoginEndpoint,
+    session: &SessionRequest,
+) -> Result<SessionReply, DbDataError> {
+    let body = serde_json::to_string(&LoginRequest {
+        remember_me: true,
+        trusted_device: session.trusted_device.clone(),
+    })?;
+
+    let mut request = session_request("POST", endpoint, &session.authorization);
+    if let Some(code) = session.two_factor_code {
+        request = request.header("Ubi-2faCode", code);
+    }
+    if let Some(ticket) = session.remember_device_ticket {
+        request = request.header("Ubi-RememberDeviceTicket", ticket);
+    }
+
+    let response = send_session_request(client, request.json_body(body))?;
+    parse_session(&response)
+}
+
+/// Extends the session before it expires. The remember-me and
+/// remember-device tickets are kept when the server does not hand out new
+/// ones.
+pub fn refresh_session(
+    client: &HttpClient,
+    endpoint: &LoginEndpoint,
+    credentials: &LoginCredentials,
+) -> Result<LoginCredentials, DbDataError> {
+    log::info!("Refreshing ubiservices session");
+
+    let request = session_request("PUT", endpoint, &ubi_authorization(credentials))
+        .header("Ubi-SessionId", credentials.session_id.as_str());
+
+    match parse_session(&send_session_request(client, request)?)? {
+        SessionReply::Created(refreshed) => Ok(LoginCredentials {
+            user_id: refreshed.user_id.or_else(|| credentials.user_id.clone()),
+            remember_me_ticket: refreshed
+                .remember_me_ticket
+                .or_else(|| credentials.remember_me_ticket.clone()),
+            remember_device_ticket: refreshed
+                .remember_device_ticket
+                .or_else(|| credentials.remember_device_ticket.clone()),
+            clock_skew: refreshed.clock_skew.or(credentials.clock_skew),
+            ..refreshed
+        }),
+        SessionReply::TwoFactor { .. } => Err(DbDataError::Protocol(
+            "Session refresh asked for two-factor authentication".to_string(),
+        )),
+    }
+}
+
+/// Deletes the session, so it no longer counts as a login on the account.
+pub fn delete_session(
+    client: &HttpClient,
+    endpoint: &LoginEndpoint,
+    credentials: &LoginCredentials,
+) -> Result<(), DbDataError> {
+    let request = session_request("DELETE", endpoint, &ubi_authorization(credentials))
+        .header("Ubi-SessionId", credentials.session_id.as_str());
+
+    send_session_request(client, request)?;
+    log::info!("Deleted ubiservices session");
+    Ok(())
+}
+
+fn ubi_authorization(credentials: &LoginCredentials) -> String {
+    format!("Ubi_v1 t={}", credentials.ticket)
+}
+
+fn session_request(method: &str, endpoint: &LoginEndpoint, authorization: &str) -> Request {
+    Request::new(method, endpoint.url())
+        .header("User-Agent", USER_AGENT)
+        .header("Authorization", authorization)
+        .header("Ubi-AppId", APP_ID)
+        .header("Ubi-RequestedPlatformType", "uplay")
+}
+
+/// Sends `request` and returns the successful response, or the ubiservices
+/// error.
+fn send_session_request(client: &HttpClient, request: Request) -> Result<Response, DbDataError> {
+    let response = client.send(request)?;
+    let response_body = response.text();
+
+    if !response.is_success() {
+        let error: Option<ErrorResponse> = serde_json::from_str(&response_body).ok();
+        let (error_code, message) = match error {
+            Some(error) => (error.error_code, error.message.unwrap_or(response_body)),
+            None => (None, response_body),
+        };
+        return Err(DbDataError::Login {
+            status: response.status,
+            error_code,
+            message,
+        });
+    }
+
+    Ok(response)
+}
+
+fn parse_session(response: &Response) -> Result<SessionReply, DbDataError> {
+    log::info!("Parsing response...");
+    let login_response: LoginResponse = serde_json::from_str(&response.text())?;
+
+    if login_response.ticket.is_none()
+        && let Some(ticket) = login_response.two_factor_authentication_ticket
+    {
+        return Ok(SessionReply::TwoFactor {
+            ticket,
+            methods: login_response
+                .code_generation_preference
+                .unwrap_or_default(),
+        });
+    }
+
+    let ticket = login_response
+        .ticket
+        .ok_or_else(|| DbDataError::Protocol("Login response missing ticket".to_string()))?;
+    let session_id = login_response
+        .session_id
+        .ok_or_else(|| DbDataError::Protocol("Login response missing session_id".to_string()))?;
+    let lifetime = session_lifetime(
+        login_response.expiration.as_deref(),
+        login_response.server_time.as_deref(),
+    );
+
+    log::info!(
+        "Login successful for user: {:?}, session expires in {:?}",
+        login_response.name_on_platform,
+        lifetime
+    );
+
+    Ok(SessionReply::Created(LoginCredentials {
+        ticket,
+        session_id,
+        user_id: login_response.user_id,
+        remember_me_ticket: login_response.remember_me_ticket,
+        remember_device_ticket: login_response.remember_device_ticket,
+        expires_at: lifetime.map(|lifetime| Instant::now() + lifetime),
+        clock_skew: response
+            .header("date")
+            .and_then(time::parse_http_date)
+            .map(|date| time::clock_skew(date, SystemTime::now())),
+    }))
+}
+
+/// How long the session lasts, measured against the server's clock when it
+/// sent one so a wrong local clock does not matter.
+fn session_lifetime(expiration: Option<&str>, server_time: Option<&str>) -> Option<Duration> {
+    let expiration = time::parse_rfc3339(expiration?)?;
+    let now = server_time
+        .and_then(time::parse_rfc3339)
+        .unwrap_or_else(SystemTime::now);
+
+    Some(expiration.duration_since(now).unwrap_or_default())
+}
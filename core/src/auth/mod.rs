@@ -0,0 +1,16 @@
+mod demux;
+mod flow;
+mod login;
+mod progress;
+mod session;
+pub mod transport;
+mod two_factor;
+mod worker;
+
+pub use demux::*;
+pub use flow::*;
+pub use login::*;
+pub use progress::*;
+pub use session::*;
+pub use two_factor::*;
+pub use worker::*;
@@ -0,0 +1,119 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::error::DbDataError;
+
+/// A step of the authentication flow, in the order they run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Stage {
+    /// Creating the ubiservices session over HTTP.
+    Login,
+    /// Connecting to demux. For a [`DemuxSession`](super::DemuxSession) this
+    /// includes pushing the version and authenticating.
+    DemuxConnect,
+    PushVersion,
+    Authenticate,
+    /// Listing the owned games and checking the app is one of them.
+    OwnershipInitialize,
+    OwnershipToken,
+    DenuvoToken,
+    /// Validating the owned DLCs. A failure here does not fail the flow.
+    OwnershipListToken,
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Stage::Login => "Logging in",
+            Stage::DemuxConnect => "Connecting to demux",
+            Stage::PushVersion => "Pushing the client version",
+            Stage::Authenticate => "Authenticating with demux",
+            Stage::OwnershipInitialize => "Checking ownership",
+            Stage::OwnershipToken => "Requesting the ownership token",
+            Stage::DenuvoToken => "Requesting the game token",
+            Stage::OwnershipListToken => "Validating DLCs",
+        };
+        f.write_str(name)
+    }
+}
+
+/// What a [`ProgressSink`] is told as the flow moves along.
+#[derive(Debug)]
+pub enum ProgressEvent<'a> {
+    Started(Stage),
+    Finished {
+        stage: Stage,
+        elapsed: Duration,
+    },
+    Failed {
+        stage: Stage,
+        elapsed: Duration,
+        error: &'a DbDataError,
+    },
+}
+
+impl ProgressEvent<'_> {
+    pub fn stage(&self) -> Stage {
+        match self {
+            ProgressEvent::Started(stage)
+            | ProgressEvent::Finished { stage, .. }
+            | ProgressEvent::Failed { stage, .. } => *stage,
+        }
+    }
+}
+
+/// Receives progress events from the flow.
+///
+/// The default logs them; a dialog, a CLI spinner or a test can plug in
+/// their own, and closures work too.
+pub trait ProgressSink: Send + Sync {
+    fn event(&self, event: &ProgressEvent);
+}
+
+impl<F: Fn(&ProgressEvent) + Send + Sync> ProgressSink for F {
+    fn event(&self, event: &ProgressEvent) {
+        self(event)
+    }
+}
+
+/// Writes every event to the log.
+pub struct LogProgress;
+
+impl ProgressSink for LogProgress {
+    fn event(&self, event: &ProgressEvent) {
+        match event {
+            ProgressEvent::Started(stage) => log::info!("{}", stage),
+            ProgressEvent::Finished { stage, elapsed } => {
+                log::info!("{} done in {:?}", stage, elapsed)
+            }
+            ProgressEvent::Failed {
+                stage,
+                elapsed,
+                error,
+            } => log::warn!("{} failed after {:?}: {}", stage, elapsed, error),
+        }
+    }
+}
+
+/// Runs `f` as `stage`, telling `sink` when it starts and how it ended.
+pub(crate) fn run_stage<T>(
+    sink: &dyn ProgressSink,
+    stage: Stage,
+    f: impl FnOnce() -> Result<T, DbDataError>,
+) -> Result<T, DbDataError> {
+    sink.event(&ProgressEvent::Started(stage));
+    let started = Instant::now();
+
+    let result = f();
+    let elapsed = started.elapsed();
+    match &result {
+        Ok(_) => sink.event(&ProgressEvent::Finished { stage, elapsed }),
+        Err(error) => sink.event(&ProgressEvent::Failed {
+            stage,
+            elapsed,
+            error,
+        }),
+    }
+
+    result
+}
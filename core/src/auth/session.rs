@@ -0,0 +1,383 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use super::{DemuxSocket, ServiceLink};
+use crate::config::NetworkConfig;
+use crate::error::DbDataError;
+use crate::proto::demux::connection_closed_push::ConnectionErrorCode;
+
+/// Opens a fresh, unauthenticated demux connection.
+pub type Connector = Box<dyn Fn() -> Result<DemuxSocket, DbDataError> + Send + Sync>;
+
+/// Timing of keep-alives and reconnect attempts.
+#[derive(Debug, Clone)]
+pub struct SessionOptions {
+    pub keep_alive_interval: Duration,
+    /// Delay before the second connection attempt; doubled after every
+    /// further failure up to `max_backoff`.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Connection attempts per reconnect before giving up.
+    pub max_attempts: u32,
+}
+
+impl Default for SessionOptions {
+    fn default() -> Self {
+        Self {
+            keep_alive_interval: Duration::from_secs(30),
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+            max_attempts: 5,
+        }
+    }
+}
+
+/// An authenticated demux connection and the real ids of the service
+/// connections opened on it.
+struct Link {
+    socket: Arc<DemuxSocket>,
+    connections: HashMap<u32, u32>,
+}
+
+struct Inner {
+    connector: Connector,
+    ticket: Mutex<String>,
+    options: SessionOptions,
+    /// Only held to read or swap the link, never across network calls.
+    link: Mutex<Option<Link>>,
+    /// Held while reconnecting or opening a service connection, so only one
+    /// thread does it at a time without blocking requests on the link.
+    connecting: Mutex<()>,
+    /// Service names by session connection id minus one.
+    services: Mutex<Vec<String>>,
+    stopped: Mutex<bool>,
+    wake: Condvar,
+}
+
+/// A demux connection that lives as long as the game.
+///
+/// Keep-alives are sent on a timer. When the connection drops, the session
+/// reconnects with exponential backoff, pushes the client version,
+/// authenticates again and reopens every service connection. Connection ids
+/// handed out by the session stay valid across reconnects.
+pub struct DemuxSession {
+    inner: Arc<Inner>,
+    keep_alive: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl DemuxSession {
+    /// Connects to the demux server configured in `network` and
+    /// authenticates with `ticket`.
+    pub fn connect(network: &NetworkConfig, ticket: &str) -> Result<Self, DbDataError> {
+        let network = network.clone();
+        Self::start(
+            Box::new(move || DemuxSocket::connect(&network)),
+            ticket,
+            SessionOptions::default(),
+        )
+    }
+
+    /// Starts a session over connections made by `connector`.
+    pub fn start(
+        connector: Connector,
+        ticket: &str,
+        options: SessionOptions,
+    ) -> Result<Self, DbDataError> {
+        let inner = Arc::new(Inner {
+            connector,
+            ticket: Mutex::new(ticket.to_string()),
+            options,
+            link: Mutex::new(None),
+            connecting: Mutex::new(()),
+            services: Mutex::new(Vec::new()),
+            stopped: Mutex::new(false),
+            wake: Condvar::new(),
+        });
+
+        let link = inner.reconnect()?;
+        *inner.link.lock().unwrap() = Some(link);
+
+        let thread_inner = inner.clone();
+        let keep_alive = thread::Builder::new()
+            .name("demux-keep-alive".to_string())
+            .spawn(move || keep_alive_loop(thread_inner))?;
+
+        Ok(Self {
+            inner,
+            keep_alive: Mutex::new(Some(keep_alive)),
+        })
+    }
+
+    /// Uses `ticket` for every later authentication, e.g. after the
+    /// ubiservices session was refreshed.
+    pub fn set_ticket(&self, ticket: &str) {
+        *self.inner.ticket.lock().unwrap() = ticket.to_string();
+    }
+
+    /// Stops the keep-alive timer and disconnects.
+    pub fn close(&self) {
+        *self.inner.stopped.lock().unwrap() = true;
+        self.inner.wake.notify_all();
+
+        if let Some(handle) = self.keep_alive.lock().unwrap().take() {
+            let _ = handle.join();
+        }
+        if let Some(link) = self.inner.link.lock().unwrap().take() {
+            link.socket.disconnect();
+        }
+    }
+}
+
+impl Drop for DemuxSession {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+impl ServiceLink for DemuxSession {
+    fn open_connection(&self, service_name: &str) -> Result<u32, DbDataError> {
+        let connection_id = {
+            let mut services = self.inner.services.lock().unwrap();
+            match services.iter().position(|s| s == service_name) {
+                Some(i) => i as u32 + 1,
+                None => {
+                    services.push(service_name.to_string());
+                    services.len() as u32
+                }
+            }
+        };
+
+        self.inner.resolve(connection_id)?;
+        Ok(connection_id)
+    }
+
+    fn send_service_data(&self, connection_id: u32, data: &[u8]) -> Result<Vec<u8>, DbDataError> {
+        let (socket, real_id) = self.inner.resolve(connection_id)?;
+        let error = match socket.send_service_data(real_id, data) {
+            Err(
+                e @ (DbDataError::Io(_)
+                | DbDataError::ConnectionClosed(_)
+                | DbDataError::ReplyTimeout),
+            ) => e,
+            result => return result,
+        };
+
+        if matches!(error, DbDataError::ReplyTimeout) && !socket.is_closed() {
+            // A slow reply says nothing about the link. A late answer to the
+            // first request answers the repeat just as well, and whatever is
+            // left over is dropped as stale before the next request.
+            log::warn!(
+                "No reply on service connection {} in time, asking again",
+                connection_id
+            );
+        } else if socket.is_closed() || is_link_failure(&error) {
+            log::warn!("Demux connection lost ({}), reconnecting", error);
+            self.inner.drop_link(&socket);
+        } else {
+            log::warn!("Service connection {} closed ({})", connection_id, error);
+            self.inner.forget_connection(&socket, connection_id);
+        }
+
+        if !error.is_retryable() {
+            return Err(error);
+        }
+        let (socket, real_id) = self.inner.resolve(connection_id)?;
+        socket.send_service_data(real_id, data)
+    }
+
+    fn receive_service_data(&self, connection_id: u32) -> Result<Vec<u8>, DbDataError> {
+        let (socket, real_id) = self.inner.resolve(connection_id)?;
+        socket.receive_service_data(real_id)
+    }
+}
+
+/// Whether `error` means the demux connection itself has to be replaced, as
+/// opposed to a single service connection.
+fn is_link_failure(error: &DbDataError) -> bool {
+    matches!(
+        error,
+        DbDataError::Io(_)
+            | DbDataError::ConnectionClosed(
+                None | Some(ConnectionErrorCode::ConnectionNoAuthFromClient)
+            )
+    )
+}
+
+impl Inner {
+    /// Returns the current socket and the real id behind `connection_id`,
+    /// reconnecting or opening the service connection first if needed.
+    fn resolve(&self, connection_id: u32) -> Result<(Arc<DemuxSocket>, u32), DbDataError> {
+        if let Some(found) = self.lookup(connection_id) {
+            return Ok(found);
+        }
+
+        let _connecting = self.connecting.lock().unwrap();
+        // Another thread may have reconnected or opened it while we waited.
+        if let Some(found) = self.lookup(connection_id) {
+            return Ok(found);
+        }
+        let socket = match self.live_socket() {
+            Some(socket) => socket,
+            None => {
+                self.install(self.reconnect()?)?;
+                // A new link reopens every service the session knows.
+                if let Some(found) = self.lookup(connection_id) {
+                    return Ok(found);
+                }
+                self.live_socket()
+                    .ok_or(DbDataError::ConnectionClosed(None))?
+            }
+        };
+
+        let service_name = self
+            .services
+            .lock()
+            .unwrap()
+            .get(connection_id as usize - 1)
+            .cloned()
+            .ok_or_else(|| {
+                DbDataError::Protocol(format!("Unknown service connection {}", connection_id))
+            })?;
+        let real_id = socket.open_connection(&service_name)?;
+
+        let mut link = self.link.lock().unwrap();
+        if let Some(link) = link.as_mut()
+            && Arc::ptr_eq(&link.socket, &socket)
+        {
+            link.connections.insert(connection_id, real_id);
+        }
+        Ok((socket, real_id))
+    }
+
+    /// The current socket and the real id behind `connection_id`, if the
+    /// link is up and the service already open on it.
+    fn lookup(&self, connection_id: u32) -> Option<(Arc<DemuxSocket>, u32)> {
+        let link = self.link.lock().unwrap();
+        let link = link.as_ref().filter(|link| !link.socket.is_closed())?;
+        let &real_id = link.connections.get(&connection_id)?;
+        Some((link.socket.clone(), real_id))
+    }
+
+    fn live_socket(&self) -> Option<Arc<DemuxSocket>> {
+        let link = self.link.lock().unwrap();
+        link.as_ref()
+            .filter(|link| !link.socket.is_closed())
+            .map(|link| link.socket.clone())
+    }
+
+    /// Makes `new` the current link, unless the session was closed while it
+    /// was being established.
+    fn install(&self, new: Link) -> Result<(), DbDataError> {
+        let mut link = self.link.lock().unwrap();
+        if *self.stopped.lock().unwrap() {
+            new.socket.disconnect();
+            return Err(DbDataError::ConnectionClosed(None));
+        }
+        *link = Some(new);
+        Ok(())
+    }
+
+    /// Forgets `socket` if it is still the current one, so the next call
+    /// reconnects.
+    fn drop_link(&self, socket: &Arc<DemuxSocket>) {
+        let mut link = self.link.lock().unwrap();
+        if link
+            .as_ref()
+            .is_some_and(|link| Arc::ptr_eq(&link.socket, socket))
+        {
+            socket.disconnect();
+            *link = None;
+        }
+    }
+
+    /// Forgets the real id behind `connection_id` so the next call opens
+    /// the service again.
+    fn forget_connection(&self, socket: &Arc<DemuxSocket>, connection_id: u32) {
+        let mut link = self.link.lock().unwrap();
+        if let Some(link) = link.as_mut()
+            && Arc::ptr_eq(&link.socket, socket)
+        {
+            link.connections.remove(&connection_id);
+        }
+    }
+
+    /// Connects with exponential backoff between attempts.
+    fn reconnect(&self) -> Result<Link, DbDataError> {
+        let mut delay = self.options.initial_backoff;
+        let mut attempt = 1;
+        loop {
+            match self.establish() {
+                Ok(link) => return Ok(link),
+                Err(e) if attempt < self.options.max_attempts && e.is_retryable() => {
+                    log::warn!(
+                        "Demux connection attempt {} failed: {}, retrying in {:?}",
+                        attempt,
+                        e,
+                        delay
+                    );
+                    if self.sleep(delay) {
+                        return Err(e);
+                    }
+                    delay = (delay * 2).min(self.options.max_backoff);
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn establish(&self) -> Result<Link, DbDataError> {
+        let socket = (self.connector)()?;
+        socket.push_version()?;
+        let ticket = self.ticket.lock().unwrap().clone();
+        socket.authenticate(&ticket, true)?;
+
+        let mut connections = HashMap::new();
+        for (i, service_name) in self.services.lock().unwrap().iter().enumerate() {
+            connections.insert(i as u32 + 1, socket.open_connection(service_name)?);
+        }
+
+        log::info!(
+            "Demux session established with {} service connections",
+            connections.len()
+        );
+        Ok(Link {
+            socket: Arc::new(socket),
+            connections,
+        })
+    }
+
+    /// Waits for `timeout` or until the session is closed. Returns whether
+    /// it was closed.
+    fn sleep(&self, timeout: Duration) -> bool {
+        let stopped = self.stopped.lock().unwrap();
+        let (stopped, _) = self
+            .wake
+            .wait_timeout_while(stopped, timeout, |stopped| !*stopped)
+            .unwrap();
+        *stopped
+    }
+}
+
+fn keep_alive_loop(inner: Arc<Inner>) {
+    while !inner.sleep(inner.options.keep_alive_interval) {
+        if let Some(socket) = inner.live_socket() {
+            if let Err(e) = socket.send_keep_alive() {
+                log::warn!("Failed to send keep-alive: {}", e);
+                inner.drop_link(&socket);
+            }
+            continue;
+        }
+
+        let _connecting = inner.connecting.lock().unwrap();
+        if inner.live_socket().is_some() {
+            continue;
+        }
+        log::info!("Demux connection lost, reconnecting");
+        if let Err(e) = inner.reconnect().and_then(|link| inner.install(link)) {
+            log::warn!("Demux reconnect failed: {}", e);
+        }
+    }
+}
@@ -0,0 +1,358 @@
+use rustls::StreamOwned;
+use rustls::pki_types::{CertificateDer, pem::PemObject};
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::sync::mpsc::{Receiver, Sender, channel};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::error::DbDataError;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Timeouts and trust roots applied to outgoing connections.
+#[derive(Debug, Clone)]
+pub struct ConnectOptions {
+    pub connect_timeout: Duration,
+    /// Applied to both reads and writes.
+    pub read_timeout: Duration,
+    pub roots: Arc<rustls::RootCertStore>,
+}
+
+impl Default for ConnectOptions {
+    fn default() -> Self {
+        Self {
+            connect_timeout: DEFAULT_TIMEOUT,
+            read_timeout: DEFAULT_TIMEOUT,
+            roots: Arc::new(root_store(None).unwrap()),
+        }
+    }
+}
+
+/// Builds a root store from the webpki roots plus every certificate in the
+/// optional PEM bundle.
+pub fn root_store(ca_bundle: Option<&Path>) -> Result<rustls::RootCertStore, DbDataError> {
+    let mut root_store = rustls::RootCertStore::empty();
+    root_store.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+
+    if let Some(path) = ca_bundle {
+        let mut added = 0;
+        for cert in CertificateDer::pem_file_iter(path).map_err(|e| {
+            DbDataError::Config(format!("Failed to read CA bundle {:?}: {}", path, e))
+        })? {
+            let cert = cert.map_err(|e| {
+                DbDataError::Config(format!("Invalid certificate in {:?}: {}", path, e))
+            })?;
+            root_store.add(cert)?;
+            added += 1;
+        }
+        log::info!("Added {} extra trust roots from {:?}", added, path);
+    }
+
+    Ok(root_store)
+}
+
+/// A bidirectional byte stream the demux framing can run over.
+pub trait Transport: Read + Write + Send {
+    /// Closes the stream in both directions. The peer sees end-of-file.
+    fn shutdown(&mut self) -> io::Result<()>;
+
+    /// Splits the stream so one thread can read while others write.
+    fn split(self: Box<Self>) -> io::Result<(Box<dyn Read + Send>, Box<dyn WriteHalf>)>;
+}
+
+/// The sending half of a split [`Transport`].
+pub trait WriteHalf: Write + Send {
+    /// Closes the stream in both directions. The peer sees end-of-file.
+    fn shutdown(&mut self) -> io::Result<()>;
+}
+
+impl Transport for TcpStream {
+    fn shutdown(&mut self) -> io::Result<()> {
+        TcpStream::shutdown(self, Shutdown::Both)
+    }
+
+    fn split(self: Box<Self>) -> io::Result<(Box<dyn Read + Send>, Box<dyn WriteHalf>)> {
+        let reader = self.try_clone()?;
+        Ok((Box::new(reader), self))
+    }
+}
+
+impl WriteHalf for TcpStream {
+    fn shutdown(&mut self) -> io::Result<()> {
+        TcpStream::shutdown(self, Shutdown::Both)
+    }
+}
+
+pub type TlsStream = StreamOwned<rustls::ClientConnection, TcpStream>;
+
+impl Transport for TlsStream {
+    fn shutdown(&mut self) -> io::Result<()> {
+        self.conn.send_close_notify();
+        let _ = self.flush();
+        self.sock.shutdown(Shutdown::Both)
+    }
+
+    /// Finishes the handshake, then shares the TLS state between a reader
+    /// and a writer that each own a handle to the socket.
+    fn split(self: Box<Self>) -> io::Result<(Box<dyn Read + Send>, Box<dyn WriteHalf>)> {
+        let StreamOwned { mut conn, mut sock } = *self;
+        while conn.is_handshaking() {
+            conn.complete_io(&mut sock)?;
+        }
+
+        let conn = Arc::new(Mutex::new(conn));
+        let reader = TlsReadHalf {
+            conn: conn.clone(),
+            sock: sock.try_clone()?,
+            pending: Vec::new(),
+        };
+        Ok((Box::new(reader), Box::new(TlsWriteHalf { conn, sock })))
+    }
+}
+
+struct TlsReadHalf {
+    conn: Arc<Mutex<rustls::ClientConnection>>,
+    sock: TcpStream,
+    /// Bytes read from the socket that rustls has not taken yet.
+    pending: Vec<u8>,
+}
+
+impl Read for TlsReadHalf {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if !self.pending.is_empty() {
+                let mut conn = self.conn.lock().unwrap();
+                let mut rd = self.pending.as_slice();
+                let used = conn.read_tls(&mut rd)?;
+                self.pending.drain(..used);
+                conn.process_new_packets().map_err(io::Error::other)?;
+                // Post-handshake messages may need an answer.
+                while conn.wants_write() {
+                    conn.write_tls(&mut self.sock)?;
+                }
+            }
+
+            match self.conn.lock().unwrap().reader().read(buf) {
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                result => return result,
+            }
+
+            if self.pending.is_empty() {
+                // Read from the socket without holding the lock, so writers
+                // are not blocked while we wait for the server.
+                let mut chunk = [0u8; 16 * 1024];
+                let n = self.sock.read(&mut chunk)?;
+                if n == 0 {
+                    return Ok(0);
+                }
+                self.pending.extend_from_slice(&chunk[..n]);
+            }
+        }
+    }
+}
+
+struct TlsWriteHalf {
+    conn: Arc<Mutex<rustls::ClientConnection>>,
+    sock: TcpStream,
+}
+
+impl Write for TlsWriteHalf {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut conn = self.conn.lock().unwrap();
+        let n = conn.writer().write(buf)?;
+        while conn.wants_write() {
+            conn.write_tls(&mut self.sock)?;
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        conn.writer().flush()?;
+        while conn.wants_write() {
+            conn.write_tls(&mut self.sock)?;
+        }
+        Ok(())
+    }
+}
+
+impl WriteHalf for TlsWriteHalf {
+    fn shutdown(&mut self) -> io::Result<()> {
+        {
+            let mut conn = self.conn.lock().unwrap();
+            conn.send_close_notify();
+            while conn.wants_write() {
+                if conn.write_tls(&mut self.sock).is_err() {
+                    break;
+                }
+            }
+        }
+        self.sock.shutdown(Shutdown::Both)
+    }
+}
+
+/// Opens a plain TCP connection, trying every resolved address in turn.
+pub fn connect_tcp(
+    host: &str,
+    port: u16,
+    options: &ConnectOptions,
+) -> Result<TcpStream, DbDataError> {
+    let mut last_error = None;
+    let mut connected = None;
+    for addr in (host, port).to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, options.connect_timeout) {
+            Ok(stream) => {
+                connected = Some(stream);
+                break;
+            }
+            Err(e) => last_error = Some(e),
+        }
+    }
+
+    let tcp_stream = match (connected, last_error) {
+        (Some(stream), _) => stream,
+        (None, Some(e)) => {
+            log::warn!("Failed to connect to {}:{}: {}", host, port, e);
+            return Err(e.into());
+        }
+        (None, None) => {
+            return Err(DbDataError::Io(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} did not resolve to any address", host),
+            )));
+        }
+    };
+
+    tcp_stream.set_nodelay(true)?;
+    tcp_stream.set_read_timeout(Some(options.read_timeout))?;
+    tcp_stream.set_write_timeout(Some(options.read_timeout))?;
+    Ok(tcp_stream)
+}
+
+/// Opens a TLS connection verified against `options.roots`.
+pub fn connect_tls(
+    host: &str,
+    port: u16,
+    versions: &[&'static rustls::SupportedProtocolVersion],
+    options: &ConnectOptions,
+) -> Result<TlsStream, DbDataError> {
+    let tcp_stream = connect_tcp(host, port, options)?;
+
+    let config = rustls::ClientConfig::builder_with_protocol_versions(versions)
+        .with_root_certificates(options.roots.clone())
+        .with_no_client_auth();
+
+    let server_name = host
+        .to_string()
+        .try_into()
+        .map_err(|_| DbDataError::Config(format!("Invalid host name: {}", host)))?;
+    let client = rustls::ClientConnection::new(Arc::new(config), server_name)?;
+    Ok(StreamOwned::new(client, tcp_stream))
+}
+
+/// One end of an in-memory pipe created by [`duplex`].
+pub struct MemoryStream {
+    tx: Option<Sender<Vec<u8>>>,
+    rx: Receiver<Vec<u8>>,
+    pending: VecDeque<u8>,
+}
+
+/// Creates a connected pair of in-memory streams. Bytes written to one end
+/// are read from the other; shutting down or dropping one end makes reads on
+/// the other return end-of-file.
+pub fn duplex() -> (MemoryStream, MemoryStream) {
+    let (a_tx, b_rx) = channel();
+    let (b_tx, a_rx) = channel();
+
+    (
+        MemoryStream {
+            tx: Some(a_tx),
+            rx: a_rx,
+            pending: VecDeque::new(),
+        },
+        MemoryStream {
+            tx: Some(b_tx),
+            rx: b_rx,
+            pending: VecDeque::new(),
+        },
+    )
+}
+
+impl Read for MemoryStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        while self.pending.is_empty() {
+            match self.rx.recv() {
+                Ok(chunk) => self.pending.extend(chunk),
+                Err(_) => return Ok(0),
+            }
+        }
+
+        let n = buf.len().min(self.pending.len());
+        for (dst, src) in buf.iter_mut().zip(self.pending.drain(..n)) {
+            *dst = src;
+        }
+        Ok(n)
+    }
+}
+
+impl Write for MemoryStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let tx = self
+            .tx
+            .as_ref()
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotConnected))?;
+        tx.send(buf.to_vec())
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for MemoryStream {
+    fn shutdown(&mut self) -> io::Result<()> {
+        self.tx = None;
+        Ok(())
+    }
+
+    fn split(mut self: Box<Self>) -> io::Result<(Box<dyn Read + Send>, Box<dyn WriteHalf>)> {
+        let writer = MemoryWriteHalf { tx: self.tx.take() };
+        Ok((self, Box::new(writer)))
+    }
+}
+
+struct MemoryWriteHalf {
+    tx: Option<Sender<Vec<u8>>>,
+}
+
+impl Write for MemoryWriteHalf {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let tx = self
+            .tx
+            .as_ref()
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotConnected))?;
+        tx.send(buf.to_vec())
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl WriteHalf for MemoryWriteHalf {
+    fn shutdown(&mut self) -> io::Result<()> {
+        self.tx = None;
+        Ok(())
+    }
+}
@@ -0,0 +1,57 @@
+use std::io::{self, BufRead, Write};
+
+/// What the user is asked for when ubiservices wants a second factor.
+#[derive(Debug, Clone)]
+pub struct TwoFactorChallenge {
+    /// Where the code can come from, most preferred first, e.g. `app` or
+    /// `email`.
+    pub methods: Vec<String>,
+    /// Starts at 1 and goes up every time a code is rejected.
+    pub attempt: u32,
+}
+
+impl TwoFactorChallenge {
+    /// A one-line request for the code, naming where to find it.
+    pub fn message(&self) -> String {
+        let source = match self.methods.first().map(String::as_str) {
+            Some("email") => "the code Ubisoft sent to your email",
+            Some("app") => "the code from your authenticator app",
+            _ => "your two-factor authentication code",
+        };
+
+        if self.attempt > 1 {
+            format!("That code was not accepted. Enter {}:", source)
+        } else {
+            format!("Enter {}:", source)
+        }
+    }
+}
+
+/// Asks the user for a two-factor authentication code.
+///
+/// The DLL shows a dialog, a CLI reads stdin and tests pass a closure.
+pub trait TwoFactorPrompt {
+    /// Returns the code, or `None` when the user cancels.
+    fn code(&self, challenge: &TwoFactorChallenge) -> Option<String>;
+}
+
+impl<F: Fn(&TwoFactorChallenge) -> Option<String>> TwoFactorPrompt for F {
+    fn code(&self, challenge: &TwoFactorChallenge) -> Option<String> {
+        self(challenge)
+    }
+}
+
+/// Reads the code from stdin. An empty line cancels.
+pub struct StdinPrompt;
+
+impl TwoFactorPrompt for StdinPrompt {
+    fn code(&self, challenge: &TwoFactorChallenge) -> Option<String> {
+        print!("{} ", challenge.message());
+        io::stdout().flush().ok()?;
+
+        let mut line = String::new();
+        io::stdin().lock().read_line(&mut line).ok()?;
+        let code = line.trim();
+        (!code.is_empty()).then(|| code.to_string())
+    }
+}
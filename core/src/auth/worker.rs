@@ -0,0 +1,103 @@
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::error::DbDataError;
+
+/// Where a [`TokenWorker`] is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorkerStatus {
+    Running,
+    Done,
+    Failed,
+}
+
+struct State<T> {
+    status: WorkerStatus,
+    result: Option<Result<T, DbDataError>>,
+}
+
+/// Runs the online flow on a background thread so the caller can poll
+/// instead of blocking.
+pub struct TokenWorker<T> {
+    shared: Arc<(Mutex<State<T>>, Condvar)>,
+    /// Kept for its OS handle, which only the Windows shim hands out.
+    #[cfg_attr(not(windows), allow(dead_code))]
+    thread: thread::JoinHandle<()>,
+}
+
+impl<T: Send + 'static> TokenWorker<T> {
+    pub fn spawn(
+        name: &str,
+        job: impl FnOnce() -> Result<T, DbDataError> + Send + 'static,
+    ) -> Result<Self, DbDataError> {
+        let shared = Arc::new((
+            Mutex::new(State {
+                status: WorkerStatus::Running,
+                result: None,
+            }),
+            Condvar::new(),
+        ));
+
+        let done = shared.clone();
+        let thread = thread::Builder::new()
+            .name(name.to_string())
+            .spawn(move || {
+                let result = job();
+                let status = match &result {
+                    Ok(_) => WorkerStatus::Done,
+                    Err(e) => {
+                        log::error!("Background token fetch failed: {}", e);
+                        WorkerStatus::Failed
+                    }
+                };
+
+                let (state, finished) = &*done;
+                *state.lock().unwrap() = State {
+                    status,
+                    result: Some(result),
+                };
+                finished.notify_all();
+            })?;
+
+        Ok(Self { shared, thread })
+    }
+
+    pub fn status(&self) -> WorkerStatus {
+        self.shared.0.lock().unwrap().status
+    }
+
+    /// Blocks until the job finishes or `timeout` passes, and returns the
+    /// status at that point.
+    pub fn wait(&self, timeout: Option<Duration>) -> WorkerStatus {
+        let (state, finished) = &*self.shared;
+        let running = |state: &mut State<T>| state.status == WorkerStatus::Running;
+
+        let state = state.lock().unwrap();
+        match timeout {
+            Some(timeout) => {
+                finished
+                    .wait_timeout_while(state, timeout, running)
+                    .unwrap()
+                    .0
+                    .status
+            }
+            None => finished.wait_while(state, running).unwrap().status,
+        }
+    }
+
+    /// Takes the result once the job has finished. Later calls return
+    /// `None`, as do calls while it is still running.
+    pub fn take(&self) -> Option<Result<T, DbDataError>> {
+        self.shared.0.lock().unwrap().result.take()
+    }
+}
+
+/// The handle of the worker thread, signalled once the job has finished. It
+/// is closed when the worker is dropped.
+#[cfg(windows)]
+impl<T> std::os::windows::io::AsRawHandle for TokenWorker<T> {
+    fn as_raw_handle(&self) -> std::os::windows::io::RawHandle {
+        self.thread.as_raw_handle()
+    }
+}
@@ -0,0 +1,49 @@
+use std::ffi::{CString, c_char};
+
+/// Copies of the cached token and DLCs that the getters of the game's
+/// interface hand out pointers to. A buffer is only replaced once the token
+/// behind it changed, so the game's pointers stay valid until the next
+/// refresh.
+#[derive(Debug, Default)]
+pub struct Buffers {
+    token: Option<CString>,
+    ownership: Option<CString>,
+    dlcs: Option<Vec<i32>>,
+}
+
+impl Buffers {
+    /// Points at a NUL-terminated copy of the game token. The length
+    /// excludes the NUL.
+    pub fn token(&mut self, token: &str) -> (*const c_char, usize) {
+        string(&mut self.token, token)
+    }
+
+    /// Points at a NUL-terminated copy of the ownership token. The length
+    /// excludes the NUL.
+    pub fn ownership(&mut self, ownership: &str) -> (*const c_char, usize) {
+        string(&mut self.ownership, ownership)
+    }
+
+    pub fn dlcs(&mut self, dlcs: &[u32]) -> (*const i32, usize) {
+        let dlcs: Vec<i32> = dlcs.iter().map(|&dlc| dlc as i32).collect();
+        if self.dlcs.as_ref() != Some(&dlcs) {
+            self.dlcs = Some(dlcs);
+        }
+        let buffer = self.dlcs.as_ref().unwrap();
+        (buffer.as_ptr(), buffer.len())
+    }
+}
+
+/// Points at `value`, replacing the buffer first if it holds something
+/// else. NULs in `value` are dropped, as they would cut the string short.
+fn string(buffer: &mut Option<CString>, value: &str) -> (*const c_char, usize) {
+    let value = value.replace('\0', "");
+    if buffer
+        .as_ref()
+        .is_none_or(|b| b.as_bytes() != value.as_bytes())
+    {
+        *buffer = Some(CString::new(value).unwrap_or_default());
+    }
+    let buffer = buffer.as_ref().unwrap();
+    (buffer.as_ptr(), buffer.as_bytes().len())
+}
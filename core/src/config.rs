@@ -0,0 +1,276 @@
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
+};
+
+use crate::auth::transport::{self, ConnectOptions};
+use crate::auth::{DEMUX_HOST, DEMUX_PORT, LOGIN_HOST, LOGIN_PATH, LoginEndpoint};
+use crate::error::DbDataError;
+use crate::remember_device::RememberDeviceStore;
+use crate::secret;
+use crate::user_cache::UserCache;
+
+#[derive(Debug, Clone)]
+pub struct DbDataConfig {
+    pub app_id: u32,
+    pub email: String,
+    pub password: String,
+    /// Saved after the first successful login so the password can be
+    /// cleared from `dbdata.ini`.
+    pub remember_me_ticket: Option<String>,
+    /// Read from the remember-device file for `email`.
+    pub remember_device_ticket: Option<String>,
+    pub network: NetworkConfig,
+}
+
+/// Endpoints, timeouts and trust roots, read from the optional `[network]`
+/// section. Missing or empty keys keep the production defaults.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NetworkConfig {
+    pub demux_host: String,
+    pub demux_port: u16,
+    pub demux_tls: bool,
+    pub login_host: String,
+    pub login_port: u16,
+    pub login_path: String,
+    pub login_tls: bool,
+    pub connect_timeout: Duration,
+    pub read_timeout: Duration,
+    /// Extra PEM CA bundle trusted on top of the webpki roots.
+    pub ca_bundle: Option<PathBuf>,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        Self {
+            demux_host: DEMUX_HOST.to_string(),
+            demux_port: DEMUX_PORT,
+            demux_tls: true,
+            login_host: LOGIN_HOST.to_string(),
+            login_port: 443,
+            login_path: LOGIN_PATH.to_string(),
+            login_tls: true,
+            connect_timeout: Duration::from_secs(30),
+            read_timeout: Duration::from_secs(30),
+            ca_bundle: None,
+        }
+    }
+}
+
+impl NetworkConfig {
+    /// Reads the `[network]` section. A relative `ca_bundle` is resolved
+    /// against `base`.
+    pub fn from_ini(ini: &ini::Ini, base: &Path) -> Result<Self, Box<dyn Error>> {
+        let mut network = Self::default();
+        let Some(section) = ini.section(Some("network")) else {
+            return Ok(network);
+        };
+
+        let get = |key: &str| {
+            section
+                .iter()
+                .find(|(k, _)| k.eq_ignore_ascii_case(key))
+                .map(|(_, v)| v.trim())
+                .filter(|v| !v.is_empty())
+        };
+
+        if let Some(host) = get("demux_host") {
+            network.demux_host = host.to_string();
+        }
+        if let Some(port) = get("demux_port") {
+            network.demux_port = parse_value("demux_port", port)?;
+        }
+        if let Some(tls) = get("demux_tls") {
+            network.demux_tls = parse_value("demux_tls", tls)?;
+        }
+        if let Some(host) = get("login_host") {
+            network.login_host = host.to_string();
+        }
+        if let Some(port) = get("login_port") {
+            network.login_port = parse_value("login_port", port)?;
+        }
+        if let Some(path) = get("login_path") {
+            network.login_path = path.to_string();
+        }
+        if let Some(tls) = get("login_tls") {
+            network.login_tls = parse_value("login_tls", tls)?;
+        }
+        if let Some(secs) = get("connect_timeout") {
+            network.connect_timeout = Duration::from_secs(parse_value("connect_timeout", secs)?);
+        }
+        if let Some(secs) = get("read_timeout") {
+            network.read_timeout = Duration::from_secs(parse_value("read_timeout", secs)?);
+        }
+        if let Some(path) = get("ca_bundle") {
+            network.ca_bundle = Some(base.join(path));
+        }
+
+        if network.connect_timeout.is_zero() || network.read_timeout.is_zero() {
+            return Err("Timeouts in [network] must be at least 1 second".into());
+        }
+
+        Ok(network)
+    }
+
+    pub fn login_endpoint(&self) -> LoginEndpoint {
+        LoginEndpoint {
+            host: self.login_host.clone(),
+            port: self.login_port,
+            path: self.login_path.clone(),
+            tls: self.login_tls,
+        }
+    }
+
+    pub fn connect_options(&self) -> Result<ConnectOptions, DbDataError> {
+        Ok(ConnectOptions {
+            connect_timeout: self.connect_timeout,
+            read_timeout: self.read_timeout,
+            roots: Arc::new(transport::root_store(self.ca_bundle.as_deref())?),
+        })
+    }
+}
+
+/// Reads the opt-in `[user_cache]` section: the client login cache to
+/// import from and, optionally, which account in it to use.
+fn user_cache_from_ini<'a>(ini: &'a ini::Ini, base: &Path) -> Option<(PathBuf, Option<&'a str>)> {
+    let section = ini.section(Some("user_cache"))?;
+    let get = |key: &str| {
+        section
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v.trim())
+            .filter(|v| !v.is_empty())
+    };
+
+    Some((base.join(get("path")?), get("account")))
+}
+
+fn parse_value<T: FromStr>(key: &str, value: &str) -> Result<T, Box<dyn Error>> {
+    value
+        .parse()
+        .map_err(|_| format!("Invalid value for {} in [network]: {}", key, value).into())
+}
+
+impl DbDataConfig {
+    pub fn load(base: &Path) -> Result<Self, Box<dyn Error>> {
+        let store = secret::default_store(base)?;
+        let ini = secret::load_ini(base, store.as_ref())?;
+
+        if ini.section(Some("Uplay")).is_none() {
+            return Err("Missing [Uplay] section in dbdata.ini".into());
+        }
+
+        let mut email = secret::get(&ini, "Uplay", "email", store.as_ref())?.unwrap_or_default();
+        let password = secret::get(&ini, "Uplay", "password", store.as_ref())?.unwrap_or_default();
+        let mut remember_me_ticket =
+            secret::get(&ini, "Uplay", "remember_me_ticket", store.as_ref())?
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty());
+
+        if password.is_empty()
+            && remember_me_ticket.is_none()
+            && let Some((path, account)) = user_cache_from_ini(&ini, base)
+        {
+            match UserCache::load(&path).and_then(|cache| cache.select(account).cloned()) {
+                Ok(imported) => {
+                    log::info!("Using the saved login from {:?}", path);
+                    if let Some(imported_email) = imported.email {
+                        email = imported_email;
+                    }
+                    remember_me_ticket = imported.remember_me_ticket;
+                }
+                Err(e) => log::warn!("Could not import the login cache {:?}: {}", path, e),
+            }
+        }
+
+        log::info!(
+            "Loaded config: email={}, password_len={}, remember_me={}",
+            if email.is_empty() { "<empty>" } else { "<set>" },
+            password.len(),
+            if remember_me_ticket.is_some() {
+                "<set>"
+            } else {
+                "<empty>"
+            }
+        );
+
+        let remember_device_ticket = if email.is_empty() {
+            None
+        } else {
+            RememberDeviceStore::new(base)
+                .ticket(&email)
+                .unwrap_or_else(|e| {
+                    log::warn!("Could not read remember-device file: {}", e);
+                    None
+                })
+        };
+
+        let network = NetworkConfig::from_ini(&ini, base)?;
+        if network != NetworkConfig::default() {
+            log::info!(
+                "Network overrides: demux={}:{} login={}:{}{}",
+                network.demux_host,
+                network.demux_port,
+                network.login_host,
+                network.login_port,
+                network.login_path
+            );
+        }
+
+        Ok(Self {
+            app_id: 0,
+            email,
+            password,
+            remember_me_ticket,
+            remember_device_ticket,
+            network,
+        })
+    }
+
+    pub fn has_credentials(&self) -> bool {
+        self.has_password() || self.remember_me_ticket.is_some()
+    }
+
+    pub fn has_password(&self) -> bool {
+        !self.email.is_empty() && !self.password.is_empty()
+    }
+
+    /// Stores `ticket` encrypted in `[Uplay]` and clears the password next
+    /// to it.
+    pub fn save_remember_me_ticket(base: &Path, ticket: &str) -> Result<(), Box<dyn Error>> {
+        let store = secret::default_store(base)?;
+        let mut ini = secret::load_ini(base, store.as_ref())?;
+        ini.with_section(Some("Uplay"))
+            .set("remember_me_ticket", secret::seal(store.as_ref(), ticket)?)
+            .set("password", "");
+
+        ini.write_to_file(base.join("dbdata.ini"))?;
+        log::info!("Saved remember-me ticket and cleared the password in dbdata.ini");
+
+        Ok(())
+    }
+
+    pub fn exists(base: &Path) -> bool {
+        base.join("dbdata.ini").exists()
+    }
+
+    pub fn create_default(base: &Path) -> Result<(), Box<dyn Error>> {
+        let ini_path = base.join("dbdata.ini");
+        let content = r#"[Uplay]
+email=
+password=
+[token]
+token=
+ownership=
+[settings]
+dlcs=
+"#;
+
+        std::fs::write(&ini_path, content)?;
+        log::info!("Created default dbdata.ini at {:?}", ini_path);
+        Ok(())
+    }
+}
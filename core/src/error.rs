@@ -0,0 +1,324 @@
+use std::fmt;
+
+use crate::proto::demux::connection_closed_push::ConnectionErrorCode;
+use crate::proto::denuvo::rsp::Result as DenuvoResult;
+
+/// Everything that can go wrong while logging in and fetching tokens.
+#[derive(Debug)]
+pub enum DbDataError {
+    /// Network failure talking to a server.
+    Io(std::io::Error),
+    /// TLS handshake or certificate failure.
+    Tls(rustls::Error),
+    /// Invalid `[network]` settings, such as an unreadable CA bundle.
+    Config(String),
+    /// A value in `dbdata.ini` could not be encrypted or decrypted.
+    Secret(String),
+    /// The Ubisoft Connect login cache could not be imported.
+    UserCache(String),
+    /// A server answered with something we could not understand.
+    Protocol(String),
+    /// ubiservices refused to create a session.
+    Login {
+        status: u16,
+        error_code: Option<i64>,
+        message: String,
+    },
+    /// The account has two-factor authentication enabled.
+    TwoFactorRequired {
+        ticket: String,
+    },
+    /// ubiservices did not accept the two-factor codes that were entered.
+    TwoFactorCodeRejected,
+    /// The saved remember-me ticket was rejected and there is no password to
+    /// fall back to.
+    RememberMeExpired,
+    /// `dbdata.ini` has no email and password or saved login to log in with.
+    MissingCredentials,
+    /// The demux server did not accept the ubi ticket.
+    DemuxAuthRejected {
+        expired: bool,
+        banned: bool,
+    },
+    /// The demux server refused to open a service connection.
+    ServiceUnavailable(String),
+    /// The demux server did not answer a request in time. The connection
+    /// itself may still be fine.
+    ReplyTimeout,
+    /// The demux server closed the connection.
+    ConnectionClosed(Option<ConnectionErrorCode>),
+    /// The demux server says our client version is too old.
+    ClientOutdated,
+    /// The ownership service answered a request with `success: false`,
+    /// naming the request.
+    OwnershipRejected(String),
+    /// The account does not own the product.
+    NotOwned(Option<u32>),
+    ExceededActivations,
+    DenuvoTimeOut,
+    DenuvoServerError,
+    NoSessions,
+    /// Denuvo answered with the generic `Failure` result or an unknown code.
+    DenuvoFailure(i32),
+}
+
+impl DbDataError {
+    /// Maps a non-success `denuvo::rsp::Result` code.
+    pub fn from_denuvo_result(result: i32) -> Self {
+        match DenuvoResult::try_from(result) {
+            Ok(DenuvoResult::NotOwned) => DbDataError::NotOwned(None),
+            Ok(DenuvoResult::ExceededActivations) => DbDataError::ExceededActivations,
+            Ok(DenuvoResult::TimeOut) => DbDataError::DenuvoTimeOut,
+            Ok(DenuvoResult::ServerError) => DbDataError::DenuvoServerError,
+            Ok(DenuvoResult::NoSessions) => DbDataError::NoSessions,
+            _ => DbDataError::DenuvoFailure(result),
+        }
+    }
+
+    /// Whether trying the same thing again later may succeed. Permanent
+    /// errors need the user to change something first.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            DbDataError::Io(_) => true,
+            DbDataError::Tls(_) | DbDataError::Config(_) | DbDataError::Protocol(_) => false,
+            DbDataError::Secret(_) | DbDataError::UserCache(_) => false,
+            DbDataError::Login { status, .. } => *status == 429 || *status >= 500,
+            DbDataError::TwoFactorRequired { .. } | DbDataError::TwoFactorCodeRejected => false,
+            DbDataError::RememberMeExpired | DbDataError::MissingCredentials => false,
+            DbDataError::DemuxAuthRejected { expired, .. } => *expired,
+            DbDataError::ServiceUnavailable(_) => true,
+            DbDataError::ReplyTimeout => true,
+            DbDataError::ConnectionClosed(code) => matches!(
+                code,
+                None | Some(
+                    ConnectionErrorCode::ConnectionForceQuit
+                        | ConnectionErrorCode::ConnectionNoAuthFromClient
+                )
+            ),
+            DbDataError::ClientOutdated => true,
+            DbDataError::OwnershipRejected(_) => false,
+            DbDataError::NotOwned(_) | DbDataError::ExceededActivations => false,
+            DbDataError::DenuvoTimeOut | DbDataError::DenuvoServerError => true,
+            DbDataError::NoSessions => true,
+            DbDataError::DenuvoFailure(_) => false,
+        }
+    }
+
+    /// A sentence telling the user what happened and what to do about it.
+    pub fn explanation(&self) -> String {
+        match self {
+            DbDataError::Io(_) => {
+                "Could not reach the Ubisoft servers. Check your internet connection and try again."
+                    .to_string()
+            }
+            DbDataError::Tls(_) => {
+                "The secure connection to the Ubisoft servers failed. If you are behind a TLS-inspecting proxy, add its CA to ca_bundle in [network]."
+                    .to_string()
+            }
+            DbDataError::Config(message) => {
+                format!("The [network] settings in dbdata.ini are invalid: {}", message)
+            }
+            DbDataError::Secret(_) => {
+                "The saved login in dbdata.ini could not be decrypted on this machine. Enter your email and password there again and restart the game."
+                    .to_string()
+            }
+            DbDataError::UserCache(message) => {
+                format!("The Ubisoft Connect login cache in [user_cache] could not be used: {}", message)
+            }
+            DbDataError::Protocol(_) => {
+                "The Ubisoft servers sent an unexpected answer. Try again later.".to_string()
+            }
+            DbDataError::Login { status: 401, .. } => {
+                "The email or password in dbdata.ini was rejected.".to_string()
+            }
+            DbDataError::Login { status: 429, .. } => {
+                "Too many login attempts. Wait a few minutes and try again.".to_string()
+            }
+            DbDataError::Login { status, .. } if *status >= 500 => {
+                "The Ubisoft login service is having problems. Try again later.".to_string()
+            }
+            DbDataError::Login { status, message, .. } => {
+                format!("Ubisoft refused the login (HTTP {}): {}", status, message)
+            }
+            DbDataError::TwoFactorRequired { .. } => {
+                "This account has two-factor authentication enabled and a code is required."
+                    .to_string()
+            }
+            DbDataError::TwoFactorCodeRejected => {
+                "The two-factor authentication code was not accepted. Restart the game and enter a fresh code."
+                    .to_string()
+            }
+            DbDataError::RememberMeExpired => {
+                "The saved Ubisoft login has expired. Enter your password in dbdata.ini again and restart the game."
+                    .to_string()
+            }
+            DbDataError::MissingCredentials => {
+                "There is no Ubisoft login in dbdata.ini. Enter your email and password there and restart the game."
+                    .to_string()
+            }
+            DbDataError::DemuxAuthRejected { banned: true, .. } => {
+                "This account is banned from Ubisoft Connect.".to_string()
+            }
+            DbDataError::DemuxAuthRejected { expired: true, .. } => {
+                "The login session expired before it could be used. Try again.".to_string()
+            }
+            DbDataError::DemuxAuthRejected { .. } => {
+                "The Ubisoft Connect server did not accept the login session.".to_string()
+            }
+            DbDataError::ServiceUnavailable(service) => {
+                format!("The Ubisoft {} is unavailable. Try again later.", service)
+            }
+            DbDataError::ReplyTimeout => {
+                "The Ubisoft Connect server took too long to answer. Try again.".to_string()
+            }
+            DbDataError::ConnectionClosed(None) => {
+                "The Ubisoft Connect server closed the connection. Try again.".to_string()
+            }
+            DbDataError::ConnectionClosed(Some(ConnectionErrorCode::ConnectionForceQuit)) => {
+                "The Ubisoft Connect server ended the session. Try again.".to_string()
+            }
+            DbDataError::ConnectionClosed(Some(ConnectionErrorCode::ConnectionMultipleLogin)) => {
+                "This account was signed in somewhere else. Close Ubisoft Connect on the other PC, then restart the game."
+                    .to_string()
+            }
+            DbDataError::ConnectionClosed(Some(ConnectionErrorCode::ConnectionBanned)) => {
+                "This account is banned from Ubisoft Connect.".to_string()
+            }
+            DbDataError::ConnectionClosed(Some(ConnectionErrorCode::ConnectionNoAuthFromClient)) => {
+                "The Ubisoft Connect server dropped the connection because the login was not completed in time. Try again."
+                    .to_string()
+            }
+            DbDataError::ClientOutdated => {
+                "The Ubisoft Connect server still reports the client version as outdated after updating it. Try again later."
+                    .to_string()
+            }
+            DbDataError::OwnershipRejected(_) => {
+                "The Ubisoft ownership service refused to confirm this account's games. Try again later."
+                    .to_string()
+            }
+            DbDataError::NotOwned(Some(product_id)) => {
+                format!("This account does not own product {}.", product_id)
+            }
+            DbDataError::NotOwned(None) => "This account does not own the game.".to_string(),
+            DbDataError::ExceededActivations => {
+                "This game has been activated on too many machines recently. Wait for the activation limit to reset."
+                    .to_string()
+            }
+            DbDataError::DenuvoTimeOut => {
+                "The Denuvo activation service timed out. Try again.".to_string()
+            }
+            DbDataError::DenuvoServerError => {
+                "The Denuvo activation service is having problems. Try again later.".to_string()
+            }
+            DbDataError::NoSessions => {
+                "The Denuvo activation service has no session for this login. Try again."
+                    .to_string()
+            }
+            DbDataError::DenuvoFailure(code) => {
+                format!("The Denuvo activation service refused the request (result {}).", code)
+            }
+        }
+    }
+}
+
+impl fmt::Display for DbDataError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DbDataError::Io(e) => write!(f, "I/O error: {}", e),
+            DbDataError::Tls(e) => write!(f, "TLS error: {}", e),
+            DbDataError::Config(message) => write!(f, "Invalid configuration: {}", message),
+            DbDataError::Secret(message) => write!(f, "Secret store error: {}", message),
+            DbDataError::UserCache(message) => write!(f, "Login cache error: {}", message),
+            DbDataError::Protocol(message) => write!(f, "Protocol error: {}", message),
+            DbDataError::Login {
+                status,
+                error_code,
+                message,
+            } => {
+                write!(f, "Login failed with status {}", status)?;
+                if let Some(code) = error_code {
+                    write!(f, " (error code {})", code)?;
+                }
+                write!(f, ": {}", message)
+            }
+            DbDataError::TwoFactorRequired { .. } => {
+                write!(f, "Two-factor authentication is required for this account")
+            }
+            DbDataError::TwoFactorCodeRejected => {
+                write!(f, "Two-factor authentication code was rejected")
+            }
+            DbDataError::RememberMeExpired => write!(f, "Remember-me ticket was rejected"),
+            DbDataError::MissingCredentials => write!(f, "No credentials in dbdata.ini"),
+            DbDataError::DemuxAuthRejected { expired, banned } => write!(
+                f,
+                "Demux authentication failed (expired={}, banned={})",
+                expired, banned
+            ),
+            DbDataError::ServiceUnavailable(service) => {
+                write!(f, "Failed to open connection to {}", service)
+            }
+            DbDataError::ReplyTimeout => write!(f, "Timed out waiting for the demux server"),
+            DbDataError::ConnectionClosed(Some(code)) => {
+                write!(f, "Connection was closed by server: {}", code.as_str_name())
+            }
+            DbDataError::ConnectionClosed(None) => write!(f, "Connection was closed by server"),
+            DbDataError::ClientOutdated => write!(f, "Client version is outdated"),
+            DbDataError::OwnershipRejected(request) => {
+                write!(f, "Ownership service rejected the {} request", request)
+            }
+            DbDataError::NotOwned(Some(product_id)) => {
+                write!(f, "You do not own app {}", product_id)
+            }
+            DbDataError::NotOwned(None) => write!(f, "Denuvo request failed: NotOwned"),
+            DbDataError::ExceededActivations => {
+                write!(f, "Denuvo request failed: ExceededActivations")
+            }
+            DbDataError::DenuvoTimeOut => write!(f, "Denuvo request failed: TimeOut"),
+            DbDataError::DenuvoServerError => write!(f, "Denuvo request failed: ServerError"),
+            DbDataError::NoSessions => write!(f, "Denuvo request failed: NoSessions"),
+            DbDataError::DenuvoFailure(code) => {
+                write!(f, "Denuvo request failed with result: {}", code)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DbDataError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DbDataError::Io(e) => Some(e),
+            DbDataError::Tls(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for DbDataError {
+    fn from(e: std::io::Error) -> Self {
+        DbDataError::Io(e)
+    }
+}
+
+impl From<rustls::Error> for DbDataError {
+    fn from(e: rustls::Error) -> Self {
+        DbDataError::Tls(e)
+    }
+}
+
+impl From<base64::DecodeError> for DbDataError {
+    fn from(e: base64::DecodeError) -> Self {
+        DbDataError::Protocol(format!("Invalid base64: {}", e))
+    }
+}
+
+impl From<prost::DecodeError> for DbDataError {
+    fn from(e: prost::DecodeError) -> Self {
+        DbDataError::Protocol(format!("Invalid protobuf message: {}", e))
+    }
+}
+
+impl From<serde_json::Error> for DbDataError {
+    fn from(e: serde_json::Error) -> Self {
+        DbDataError::Protocol(format!("Invalid JSON: {}", e))
+    }
+}
@@ -0,0 +1,463 @@
+//! Minimal HTTP/1.1 client for the ubiservices REST calls.
+//!
+//! Every request uses its own connection (`Connection: close`). Responses may
+//! be framed by `Content-Length`, `Transfer-Encoding: chunked` or the end of
+//! the stream, and redirects are followed up to a fixed limit.
+
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader, Read, Write},
+};
+
+use crate::auth::transport::{self, ConnectOptions, Transport};
+use crate::error::DbDataError;
+
+const MAX_REDIRECTS: usize = 5;
+const MAX_BODY_SIZE: usize = 1024 * 1024;
+const MAX_HEADER_LINE: usize = 8 * 1024;
+const MAX_HEADERS: usize = 100;
+/// Headers that identify the account, dropped on a redirect to another host.
+const CREDENTIAL_HEADERS: &[&str] = &[
+    "authorization",
+    "ubi-sessionid",
+    "ubi-rememberdeviceticket",
+    "ubi-2facode",
+];
+
+/// An absolute `http` or `https` URL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Url {
+    pub tls: bool,
+    pub host: String,
+    pub port: u16,
+    /// Path and query, always starting with `/`.
+    pub path: String,
+}
+
+impl Url {
+    pub fn parse(url: &str) -> Result<Self, DbDataError> {
+        let (tls, rest) = if let Some(rest) = url.strip_prefix("https://") {
+            (true, rest)
+        } else if let Some(rest) = url.strip_prefix("http://") {
+            (false, rest)
+        } else {
+            return Err(DbDataError::Protocol(format!("Unsupported URL: {}", url)));
+        };
+
+        let (authority, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/"),
+        };
+
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (
+                host,
+                port.parse()
+                    .map_err(|_| DbDataError::Protocol(format!("Invalid port in URL: {}", url)))?,
+            ),
+            None => (authority, if tls { 443 } else { 80 }),
+        };
+
+        if host.is_empty() {
+            return Err(DbDataError::Protocol(format!(
+                "Missing host in URL: {}",
+                url
+            )));
+        }
+
+        Ok(Self {
+            tls,
+            host: host.to_string(),
+            port,
+            path: path.to_string(),
+        })
+    }
+
+    /// Resolves a `Location` header against this URL.
+    pub fn join(&self, location: &str) -> Result<Self, DbDataError> {
+        if location.starts_with("http://") || location.starts_with("https://") {
+            return Self::parse(location);
+        }
+        if location.starts_with("//") {
+            let scheme = if self.tls { "https:" } else { "http:" };
+            return Self::parse(&format!("{}{}", scheme, location));
+        }
+
+        let path = if location.starts_with('/') {
+            location.to_string()
+        } else {
+            let base = self.path.split('?').next().unwrap_or("/");
+            let dir = &base[..base.rfind('/').map(|i| i + 1).unwrap_or(1)];
+            format!("{}{}", dir, location)
+        };
+
+        Ok(Self {
+            path,
+            ..self.clone()
+        })
+    }
+
+    fn host_header(&self) -> String {
+        match (self.tls, self.port) {
+            (true, 443) | (false, 80) => self.host.clone(),
+            _ => format!("{}:{}", self.host, self.port),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: String,
+    pub url: Url,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    pub fn new(method: &str, url: Url) -> Self {
+        Self {
+            method: method.to_string(),
+            url,
+            headers: vec![],
+            body: vec![],
+        }
+    }
+
+    pub fn header(mut self, name: &str, value: impl Into<String>) -> Self {
+        self.headers.push((name.to_string(), value.into()));
+        self
+    }
+
+    pub fn json_body(mut self, body: String) -> Self {
+        self.headers
+            .push(("Content-Type".to_string(), "application/json".to_string()));
+        self.body = body.into_bytes();
+        self
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Response {
+    pub status: u16,
+    pub reason: String,
+    /// Header names are lower-cased; repeated headers are joined with `, `.
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .get(&name.to_ascii_lowercase())
+            .map(|v| v.as_str())
+    }
+
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HttpClient {
+    options: ConnectOptions,
+    max_redirects: usize,
+    max_body_size: usize,
+}
+
+impl HttpClient {
+    pub fn new(options: ConnectOptions) -> Self {
+        Self {
+            options,
+            max_redirects: MAX_REDIRECTS,
+            max_body_size: MAX_BODY_SIZE,
+        }
+    }
+
+    pub fn with_max_redirects(mut self, max_redirects: usize) -> Self {
+        self.max_redirects = max_redirects;
+        self
+    }
+
+    pub fn with_max_body_size(mut self, max_body_size: usize) -> Self {
+        self.max_body_size = max_body_size;
+        self
+    }
+
+    /// Sends `request`, following redirects.
+    ///
+    /// 307 and 308 repeat the request as is, other redirects turn it into a
+    /// `GET` without a body. `Authorization` and the session headers are dropped
+    /// when the host changes and redirects from `https` to `http` are refused.
+    pub fn send(&self, request: Request) -> Result<Response, DbDataError> {
+        let mut request = request;
+
+        for _ in 0..=self.max_redirects {
+            let response = self.send_once(&request)?;
+
+            if !matches!(response.status, 301 | 302 | 303 | 307 | 308) {
+                return Ok(response);
+            }
+
+            let location = response.header("location").ok_or_else(|| {
+                DbDataError::Protocol(format!("Redirect {} without Location", response.status))
+            })?;
+            let url = request.url.join(location)?;
+            log::info!(
+                "Following {} redirect to {}{}",
+                response.status,
+                url.host,
+                url.path
+            );
+
+            if request.url.tls && !url.tls {
+                return Err(DbDataError::Protocol(format!(
+                    "Refusing to follow redirect from https to {}",
+                    location
+                )));
+            }
+            if url.host != request.url.host {
+                request.headers.retain(|(name, _)| {
+                    !CREDENTIAL_HEADERS
+                        .iter()
+                        .any(|header| name.eq_ignore_ascii_case(header))
+                });
+            }
+            if !matches!(response.status, 307 | 308) {
+                request.method = "GET".to_string();
+                request.body.clear();
+                request
+                    .headers
+                    .retain(|(name, _)| !name.eq_ignore_ascii_case("content-type"));
+            }
+            request.url = url;
+        }
+
+        Err(DbDataError::Protocol(format!(
+            "Too many redirects (more than {})",
+            self.max_redirects
+        )))
+    }
+
+    fn send_once(&self, request: &Request) -> Result<Response, DbDataError> {
+        let url = &request.url;
+        log::info!("{} {}:{}{}", request.method, url.host, url.port, url.path);
+
+        let mut stream: Box<dyn Transport> = if url.tls {
+            Box::new(transport::connect_tls(
+                &url.host,
+                url.port,
+                rustls::DEFAULT_VERSIONS,
+                &self.options,
+            )?)
+        } else {
+            Box::new(transport::connect_tcp(&url.host, url.port, &self.options)?)
+        };
+
+        let mut head = format!(
+            "{} {} HTTP/1.1\r\nHost: {}\r\n",
+            request.method,
+            url.path,
+            url.host_header()
+        );
+        for (name, value) in &request.headers {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        if !request.body.is_empty() || matches!(request.method.as_str(), "POST" | "PUT") {
+            head.push_str(&format!("Content-Length: {}\r\n", request.body.len()));
+        }
+        head.push_str("Connection: close\r\n\r\n");
+
+        let mut message = head.into_bytes();
+        message.extend_from_slice(&request.body);
+        stream.write_all(&message)?;
+        stream.flush()?;
+
+        let response = read_response(
+            &mut BufReader::new(stream),
+            &request.method,
+            self.max_body_size,
+        )?;
+        log::info!("Status: {} {}", response.status, response.reason);
+        Ok(response)
+    }
+}
+
+/// Parses one HTTP/1.1 response from `reader`.
+pub fn read_response<R: BufRead>(
+    reader: &mut R,
+    method: &str,
+    max_body_size: usize,
+) -> Result<Response, DbDataError> {
+    let status_line = read_line(reader)?;
+    let mut parts = status_line.splitn(3, ' ');
+    let version = parts.next().unwrap_or_default();
+    if !version.starts_with("HTTP/1.") {
+        return Err(DbDataError::Protocol(format!(
+            "Invalid HTTP status line: {:?}",
+            status_line
+        )));
+    }
+    let status: u16 = parts.next().and_then(|s| s.parse().ok()).ok_or_else(|| {
+        DbDataError::Protocol(format!("Invalid HTTP status line: {:?}", status_line))
+    })?;
+    let reason = parts.next().unwrap_or_default().to_string();
+
+    let headers = read_headers(reader)?;
+
+    let no_body = method.eq_ignore_ascii_case("HEAD")
+        || (100..200).contains(&status)
+        || status == 204
+        || status == 304;
+
+    let chunked = headers
+        .get("transfer-encoding")
+        .map(|te| {
+            te.rsplit(',')
+                .next()
+                .is_some_and(|last| last.trim().eq_ignore_ascii_case("chunked"))
+        })
+        .unwrap_or(false);
+
+    let body = if no_body {
+        vec![]
+    } else if chunked {
+        read_chunked(reader, max_body_size)?
+    } else if let Some(length) = headers.get("content-length") {
+        let length: usize = length
+            .trim()
+            .parse()
+            .map_err(|_| DbDataError::Protocol(format!("Invalid Content-Length: {}", length)))?;
+        if length > max_body_size {
+            return Err(DbDataError::Protocol(format!(
+                "Response body of {} bytes exceeds the {} byte limit",
+                length, max_body_size
+            )));
+        }
+        let mut body = vec![0u8; length];
+        reader.read_exact(&mut body)?;
+        body
+    } else {
+        read_to_end_limited(reader, max_body_size)?
+    };
+
+    Ok(Response {
+        status,
+        reason,
+        headers,
+        body,
+    })
+}
+
+fn read_line<R: BufRead>(reader: &mut R) -> Result<String, DbDataError> {
+    let mut line = Vec::new();
+    reader
+        .by_ref()
+        .take(MAX_HEADER_LINE as u64 + 1)
+        .read_until(b'\n', &mut line)?;
+
+    if line.is_empty() {
+        return Err(DbDataError::Protocol(
+            "Connection closed before the HTTP response was complete".to_string(),
+        ));
+    }
+    if line.len() > MAX_HEADER_LINE {
+        return Err(DbDataError::Protocol(
+            "HTTP header line too long".to_string(),
+        ));
+    }
+    if line.last() != Some(&b'\n') {
+        return Err(DbDataError::Protocol(
+            "Connection closed in the middle of an HTTP header".to_string(),
+        ));
+    }
+
+    let line = String::from_utf8(line)
+        .map_err(|_| DbDataError::Protocol("HTTP header is not valid UTF-8".to_string()))?;
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+fn read_headers<R: BufRead>(reader: &mut R) -> Result<HashMap<String, String>, DbDataError> {
+    let mut headers: HashMap<String, String> = HashMap::new();
+
+    for _ in 0..=MAX_HEADERS {
+        let line = read_line(reader)?;
+        if line.is_empty() {
+            return Ok(headers);
+        }
+
+        let (name, value) = line
+            .split_once(':')
+            .ok_or_else(|| DbDataError::Protocol(format!("Malformed HTTP header: {:?}", line)))?;
+        let name = name.trim().to_ascii_lowercase();
+        let value = value.trim();
+
+        headers
+            .entry(name)
+            .and_modify(|existing| {
+                existing.push_str(", ");
+                existing.push_str(value);
+            })
+            .or_insert_with(|| value.to_string());
+    }
+
+    Err(DbDataError::Protocol(format!(
+        "More than {} HTTP headers",
+        MAX_HEADERS
+    )))
+}
+
+fn read_chunked<R: BufRead>(reader: &mut R, max_body_size: usize) -> Result<Vec<u8>, DbDataError> {
+    let mut body = Vec::new();
+
+    loop {
+        let line = read_line(reader)?;
+        let size = line.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size, 16)
+            .map_err(|_| DbDataError::Protocol(format!("Invalid chunk size: {:?}", line)))?;
+
+        if size == 0 {
+            // Trailer section, terminated by an empty line.
+            read_headers(reader)?;
+            return Ok(body);
+        }
+
+        if size > max_body_size - body.len() {
+            return Err(DbDataError::Protocol(format!(
+                "Response body exceeds the {} byte limit",
+                max_body_size
+            )));
+        }
+
+        let start = body.len();
+        body.resize(start + size, 0);
+        reader.read_exact(&mut body[start..])?;
+
+        if !read_line(reader)?.is_empty() {
+            return Err(DbDataError::Protocol(
+                "Missing CRLF after HTTP chunk".to_string(),
+            ));
+        }
+    }
+}
+
+fn read_to_end_limited<R: Read>(
+    reader: &mut R,
+    max_body_size: usize,
+) -> Result<Vec<u8>, DbDataError> {
+    let mut body = Vec::new();
+    reader
+        .take(max_body_size as u64 + 1)
+        .read_to_end(&mut body)?;
+
+    if body.len() > max_body_size {
+        return Err(DbDataError::Protocol(format!(
+            "Response body exceeds the {} byte limit",
+            max_body_size
+        )));
+    }
+    Ok(body)
+}
@@ -0,0 +1,18 @@
+//! Platform-neutral core of `dbdata`.
+//!
+//! Everything needed to log in to Ubisoft, talk to the demux server and
+//! cache tokens lives here so it can be built and tested on any platform.
+//! The Windows DLL in the workspace root is a thin shim over this crate.
+
+pub mod auth;
+pub mod buffers;
+pub mod config;
+pub mod error;
+pub mod http;
+pub mod proto;
+pub mod remember_device;
+pub mod secret;
+pub mod services;
+pub mod time;
+pub mod token;
+pub mod user_cache;
@@ -16,3 +16,16 @@ pub mod ownership {
 pub mod denuvo {
     include!(concat!(env!("OUT_DIR"), "/mg.protocol.denuvo_service.rs"));
 }
+
+/// Per-account remember-device tickets (mg.protocol.remember_device_file)
+pub mod remember_device_file {
+    include!(concat!(
+        env!("OUT_DIR"),
+        "/mg.protocol.remember_device_file.rs"
+    ));
+}
+
+/// The Ubisoft Connect client's own login cache (mg.protocol.user_dat_file)
+pub mod user_dat_file {
+    include!(concat!(env!("OUT_DIR"), "/mg.protocol.user_dat_file.rs"));
+}
@@ -0,0 +1,127 @@
+//! Remember-device tickets, kept per account in the `UserLoginCache`
+//! protobuf format of `remember_device_file.proto`.
+//!
+//! A ticket skips two-factor authentication, so the file is sealed with the
+//! [`SecretStore`](crate::secret::SecretStore) of `dbdata.ini`. Files saved
+//! in the clear by older versions are read as they are and sealed the first
+//! time they are loaded.
+
+use std::path::{Path, PathBuf};
+
+use prost::Message;
+use ring::digest::{SHA256, digest};
+
+use crate::error::DbDataError;
+use crate::proto::remember_device_file::{
+    User, UserLoginCache,
+    user::{AccountId, EmailHash, RdTicket},
+    user_login_cache::Version,
+};
+use crate::secret;
+
+const FILE_NAME: &str = "remember_device.dat";
+const VERSION: u32 = 1;
+/// Starts a sealed file. A plaintext `UserLoginCache` never starts with it.
+const SEALED_PREFIX: &[u8] = b"enc:";
+
+/// The remember-device file next to `dbdata.ini`.
+pub struct RememberDeviceStore {
+    base: PathBuf,
+    path: PathBuf,
+}
+
+impl RememberDeviceStore {
+    pub fn new(base: &Path) -> Self {
+        Self {
+            base: base.to_path_buf(),
+            path: base.join(FILE_NAME),
+        }
+    }
+
+    /// Returns the ticket saved for `email`, if any.
+    pub fn ticket(&self, email: &str) -> Result<Option<String>, DbDataError> {
+        let email_hash = email_hash(email);
+
+        Ok(self
+            .load()?
+            .users
+            .into_iter()
+            .find(|user| has_email_hash(user, &email_hash))
+            .and_then(|user| user.rd_ticket)
+            .map(|RdTicket::RdTicket(ticket)| ticket)
+            .filter(|ticket| !ticket.is_empty()))
+    }
+
+    /// Saves `ticket` for `email`, replacing the one saved before.
+    pub fn save(
+        &self,
+        email: &str,
+        account_id: Option<&str>,
+        ticket: &str,
+    ) -> Result<(), DbDataError> {
+        let email_hash = email_hash(email);
+
+        let mut cache = self.load()?;
+        cache
+            .users
+            .retain(|user| !has_email_hash(user, &email_hash));
+        cache.users.push(User {
+            account_id: account_id.map(|id| AccountId::AccountId(id.to_string())),
+            email_hash: Some(EmailHash::EmailHash(email_hash)),
+            rd_ticket: Some(RdTicket::RdTicket(ticket.to_string())),
+        });
+        cache.version = Some(Version::Version(VERSION));
+
+        self.write(&cache)?;
+        log::info!("Saved remember-device ticket to {:?}", self.path);
+
+        Ok(())
+    }
+
+    fn load(&self) -> Result<UserLoginCache, DbDataError> {
+        let data = match std::fs::read(&self.path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(UserLoginCache::default());
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        if let Some(sealed) = data.strip_prefix(SEALED_PREFIX) {
+            let store = secret::default_store(&self.base)?;
+            return Ok(UserLoginCache::decode(store.decrypt(sealed)?.as_slice())?);
+        }
+
+        let cache = UserLoginCache::decode(data.as_slice())?;
+        match self.write(&cache) {
+            Ok(()) => log::info!("Sealed plaintext remember-device file {:?}", self.path),
+            Err(e) => log::warn!("Could not seal remember-device file: {}", e),
+        }
+        Ok(cache)
+    }
+
+    fn write(&self, cache: &UserLoginCache) -> Result<(), DbDataError> {
+        let store = secret::default_store(&self.base)?;
+        let sealed = [SEALED_PREFIX, &store.encrypt(&cache.encode_to_vec())?].concat();
+        std::fs::write(&self.path, sealed)?;
+        Ok(())
+    }
+}
+
+fn has_email_hash(user: &User, email_hash: &str) -> bool {
+    matches!(&user.email_hash, Some(EmailHash::EmailHash(hash)) if hash == email_hash)
+}
+
+/// Hex SHA-256 of the lower-cased email, so the file does not reveal which
+/// accounts logged in.
+pub fn email_hash(email: &str) -> String {
+    sha256_hex(email.trim().to_lowercase().as_bytes())
+}
+
+pub(crate) fn sha256_hex(data: &[u8]) -> String {
+    digest(&SHA256, data)
+        .as_ref()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
@@ -0,0 +1,330 @@
+//! Encryption of the credentials and tokens kept in `dbdata.ini`.
+//!
+//! Sealed values are written as `enc:<base64>`. Anything without that
+//! prefix is plaintext from an older version or typed in by hand, and is
+//! sealed the next time the file is loaded.
+
+use std::{error::Error, num::NonZeroU32, path::Path};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use ring::{
+    aead::{AES_256_GCM, Aad, LessSafeKey, NONCE_LEN, Nonce, UnboundKey},
+    pbkdf2,
+    rand::{SecureRandom, SystemRandom},
+};
+
+use crate::error::DbDataError;
+
+const PREFIX: &str = "enc:";
+const KEY_FILE_NAME: &str = "dbdata.key";
+const KEY_LEN: usize = 32;
+const PBKDF2_ITERATIONS: u32 = 100_000;
+const PBKDF2_SALT: &[u8] = b"dbdata secret store";
+
+/// Keys in `dbdata.ini` whose values are kept sealed, by section. An entry
+/// also covers sections named after it with a `.` suffix, like
+/// `[token.4553]`.
+const SECRET_KEYS: &[(&str, &[&str])] = &[
+    ("Uplay", &["email", "password", "remember_me_ticket"]),
+    ("token", &["token", "ownership"]),
+];
+
+/// Encrypts and decrypts values at rest.
+pub trait SecretStore {
+    fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>, DbDataError>;
+    fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>, DbDataError>;
+}
+
+/// The store for the DLL next to `base`: DPAPI on Windows, otherwise a key
+/// derived from `DBDATA_PASSPHRASE` or kept in `dbdata.key`.
+pub fn default_store(base: &Path) -> Result<Box<dyn SecretStore>, DbDataError> {
+    #[cfg(windows)]
+    {
+        let _ = base;
+        Ok(Box::new(DpapiStore))
+    }
+
+    #[cfg(not(windows))]
+    {
+        match std::env::var("DBDATA_PASSPHRASE") {
+            Ok(passphrase) if !passphrase.is_empty() => {
+                Ok(Box::new(FileKeyStore::from_passphrase(&passphrase)))
+            }
+            _ => Ok(Box::new(FileKeyStore::open(base)?)),
+        }
+    }
+}
+
+/// AES-256-GCM with a key from a passphrase or a key file.
+pub struct FileKeyStore {
+    key: LessSafeKey,
+}
+
+impl FileKeyStore {
+    pub fn from_key(key: &[u8; KEY_LEN]) -> Self {
+        Self {
+            key: LessSafeKey::new(UnboundKey::new(&AES_256_GCM, key).expect("key length")),
+        }
+    }
+
+    /// Derives the key from `passphrase` with PBKDF2-HMAC-SHA256.
+    pub fn from_passphrase(passphrase: &str) -> Self {
+        let mut key = [0u8; KEY_LEN];
+        pbkdf2::derive(
+            pbkdf2::PBKDF2_HMAC_SHA256,
+            NonZeroU32::new(PBKDF2_ITERATIONS).unwrap(),
+            PBKDF2_SALT,
+            passphrase.as_bytes(),
+            &mut key,
+        );
+        Self::from_key(&key)
+    }
+
+    /// Reads the key from `dbdata.key` next to `dbdata.ini`, creating a
+    /// random one the first time.
+    pub fn open(base: &Path) -> Result<Self, DbDataError> {
+        let path = base.join(KEY_FILE_NAME);
+
+        match std::fs::read(&path) {
+            Ok(data) => {
+                let key: [u8; KEY_LEN] = data.try_into().map_err(|_| {
+                    DbDataError::Secret(format!("{:?} is not a {}-byte key", path, KEY_LEN))
+                })?;
+                Ok(Self::from_key(&key))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                let mut key = [0u8; KEY_LEN];
+                SystemRandom::new()
+                    .fill(&mut key)
+                    .map_err(|_| DbDataError::Secret("No random source for the key".into()))?;
+                write_private(&path, &key)?;
+                log::info!("Created secret key at {:?}", path);
+                Ok(Self::from_key(&key))
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+impl SecretStore for FileKeyStore {
+    /// Returns the random nonce followed by the ciphertext and tag.
+    fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>, DbDataError> {
+        let mut nonce = [0u8; NONCE_LEN];
+        SystemRandom::new()
+            .fill(&mut nonce)
+            .map_err(|_| DbDataError::Secret("No random source for the nonce".into()))?;
+
+        let mut in_out = plaintext.to_vec();
+        self.key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::empty(),
+                &mut in_out,
+            )
+            .map_err(|_| DbDataError::Secret("Encryption failed".into()))?;
+
+        let mut sealed = nonce.to_vec();
+        sealed.extend_from_slice(&in_out);
+        Ok(sealed)
+    }
+
+    fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>, DbDataError> {
+        if ciphertext.len() < NONCE_LEN {
+            return Err(DbDataError::Secret("Sealed value is truncated".into()));
+        }
+        let (nonce, sealed) = ciphertext.split_at(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(nonce)
+            .map_err(|_| DbDataError::Secret("Sealed value is truncated".into()))?;
+
+        let mut in_out = sealed.to_vec();
+        let plaintext = self
+            .key
+            .open_in_place(nonce, Aad::empty(), &mut in_out)
+            .map_err(|_| DbDataError::Secret("Sealed value was made with another key".into()))?;
+        Ok(plaintext.to_vec())
+    }
+}
+
+#[cfg(unix)]
+fn write_private(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    use std::{io::Write, os::unix::fs::OpenOptionsExt};
+
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)?
+        .write_all(data)
+}
+
+#[cfg(not(unix))]
+fn write_private(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    std::fs::write(path, data)
+}
+
+/// DPAPI bound to the current Windows user.
+#[cfg(windows)]
+pub struct DpapiStore;
+
+#[cfg(windows)]
+impl DpapiStore {
+    const ENTROPY: &[u8] = b"dbdata";
+
+    fn call(
+        data: &[u8],
+        f: impl FnOnce(
+            *mut winapi::um::wincrypt::DATA_BLOB,
+            *mut winapi::um::wincrypt::DATA_BLOB,
+            *mut winapi::um::wincrypt::DATA_BLOB,
+        ) -> i32,
+    ) -> Result<Vec<u8>, DbDataError> {
+        use winapi::um::{winbase::LocalFree, wincrypt::DATA_BLOB};
+
+        let mut input = DATA_BLOB {
+            cbData: data.len() as u32,
+            pbData: data.as_ptr() as *mut u8,
+        };
+        let mut entropy = DATA_BLOB {
+            cbData: Self::ENTROPY.len() as u32,
+            pbData: Self::ENTROPY.as_ptr() as *mut u8,
+        };
+        let mut output = DATA_BLOB {
+            cbData: 0,
+            pbData: std::ptr::null_mut(),
+        };
+
+        if f(&mut input, &mut entropy, &mut output) == 0 {
+            return Err(DbDataError::Secret(format!(
+                "DPAPI failed: {}",
+                std::io::Error::last_os_error()
+            )));
+        }
+
+        let result =
+            unsafe { std::slice::from_raw_parts(output.pbData, output.cbData as usize) }.to_vec();
+        unsafe { LocalFree(output.pbData as *mut _) };
+        Ok(result)
+    }
+}
+
+#[cfg(windows)]
+impl SecretStore for DpapiStore {
+    fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>, DbDataError> {
+        use winapi::um::dpapi::{CRYPTPROTECT_UI_FORBIDDEN, CryptProtectData};
+
+        Self::call(plaintext, |input, entropy, output| unsafe {
+            CryptProtectData(
+                input,
+                std::ptr::null(),
+                entropy,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                CRYPTPROTECT_UI_FORBIDDEN,
+                output,
+            )
+        })
+    }
+
+    fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>, DbDataError> {
+        use winapi::um::dpapi::{CRYPTPROTECT_UI_FORBIDDEN, CryptUnprotectData};
+
+        Self::call(ciphertext, |input, entropy, output| unsafe {
+            CryptUnprotectData(
+                input,
+                std::ptr::null_mut(),
+                entropy,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                CRYPTPROTECT_UI_FORBIDDEN,
+                output,
+            )
+        })
+    }
+}
+
+/// Whether `value` was written by [`seal`].
+pub fn is_sealed(value: &str) -> bool {
+    value.starts_with(PREFIX)
+}
+
+/// Encrypts `value` into its `enc:` form. Empty values stay empty.
+pub fn seal(store: &dyn SecretStore, value: &str) -> Result<String, DbDataError> {
+    if value.is_empty() {
+        return Ok(String::new());
+    }
+    Ok(format!(
+        "{}{}",
+        PREFIX,
+        STANDARD.encode(store.encrypt(value.as_bytes())?)
+    ))
+}
+
+/// Decrypts a value written by [`seal`]. Plaintext is returned unchanged.
+pub fn unseal(store: &dyn SecretStore, value: &str) -> Result<String, DbDataError> {
+    let Some(encoded) = value.strip_prefix(PREFIX) else {
+        return Ok(value.to_string());
+    };
+    let plaintext = store.decrypt(&STANDARD.decode(encoded.trim())?)?;
+    String::from_utf8(plaintext)
+        .map_err(|_| DbDataError::Secret("Sealed value is not UTF-8".into()))
+}
+
+/// Loads `dbdata.ini`, sealing any secrets still in plaintext and writing
+/// the file back when something changed.
+pub(crate) fn load_ini(base: &Path, store: &dyn SecretStore) -> Result<ini::Ini, Box<dyn Error>> {
+    let ini_path = base.join("dbdata.ini");
+    let mut ini = ini::Ini::load_from_file(&ini_path)
+        .map_err(|e| format!("Failed to load dbdata.ini: {}", e))?;
+
+    let mut migrated = vec![];
+    for (section, properties) in ini.iter_mut() {
+        let Some(section) = section else {
+            continue;
+        };
+        let Some((_, keys)) = SECRET_KEYS.iter().find(|(name, _)| {
+            section == *name
+                || section
+                    .strip_prefix(*name)
+                    .is_some_and(|rest| rest.starts_with('.'))
+        }) else {
+            continue;
+        };
+        for (key, value) in properties.iter_mut() {
+            if keys.iter().any(|k| k.eq_ignore_ascii_case(key))
+                && !value.is_empty()
+                && !is_sealed(value)
+            {
+                *value = seal(store, value)?;
+                migrated.push(format!("{}.{}", section, key));
+            }
+        }
+    }
+
+    if !migrated.is_empty() {
+        ini.write_to_file(&ini_path)?;
+        log::info!("Encrypted plaintext {} in dbdata.ini", migrated.join(", "));
+    }
+
+    Ok(ini)
+}
+
+/// Reads and decrypts `key` from `section`, matching the key without case.
+pub(crate) fn get(
+    ini: &ini::Ini,
+    section: &str,
+    key: &str,
+    store: &dyn SecretStore,
+) -> Result<Option<String>, Box<dyn Error>> {
+    let Some(value) = ini.section(Some(section)).and_then(|properties| {
+        properties
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v)
+    }) else {
+        return Ok(None);
+    };
+
+    unseal(store, value)
+        .map(Some)
+        .map_err(|e| format!("Could not decrypt {} in dbdata.ini: {}", key, e).into())
+}
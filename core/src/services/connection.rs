@@ -0,0 +1,123 @@
+use std::marker::PhantomData;
+
+use prost::Message;
+
+use crate::auth::ServiceLink;
+use crate::error::DbDataError;
+
+/// The `Upstream` message of a demux service, wrapping one `Req`.
+pub trait ServiceUpstream: Message {
+    type Req: ServiceRequest;
+
+    fn from_request(req: Self::Req) -> Self;
+}
+
+/// The `Downstream` message of a demux service, carrying a `Rsp`, a push or
+/// both.
+pub trait ServiceDownstream: Message + Default {
+    type Rsp: ServiceResponse;
+
+    fn into_response(self) -> Option<Self::Rsp>;
+}
+
+pub trait ServiceRequest {
+    fn set_request_id(&mut self, request_id: u32);
+}
+
+pub trait ServiceResponse {
+    fn request_id(&self) -> u32;
+
+    /// Maps a failed result code carried by the response to an error.
+    fn check(&self) -> Result<(), DbDataError> {
+        Ok(())
+    }
+}
+
+/// Implements the service traits for a prost `Upstream`/`Downstream` pair
+/// with the usual `request`/`response` and `request_id` fields.
+macro_rules! service_messages {
+    ($module:path) => {
+        const _: () = {
+            use $module as m;
+
+            impl $crate::services::ServiceUpstream for m::Upstream {
+                type Req = m::Req;
+
+                fn from_request(req: m::Req) -> Self {
+                    Self { request: Some(req) }
+                }
+            }
+
+            impl $crate::services::ServiceDownstream for m::Downstream {
+                type Rsp = m::Rsp;
+
+                fn into_response(self) -> Option<m::Rsp> {
+                    self.response
+                }
+            }
+
+            impl $crate::services::ServiceRequest for m::Req {
+                fn set_request_id(&mut self, request_id: u32) {
+                    self.request_id = request_id;
+                }
+            }
+        };
+    };
+}
+pub(crate) use service_messages;
+
+/// A connection to one demux service speaking the `Up`/`Down` protobuf pair.
+///
+/// Allocates request ids, skips pushes and stale answers until the response
+/// with the matching `request_id` arrives, and turns failed result codes into
+/// errors.
+pub struct ServiceConnection<'a, Up, Down> {
+    link: &'a dyn ServiceLink,
+    connection_id: u32,
+    request_id: u32,
+    _messages: PhantomData<fn(Up) -> Down>,
+}
+
+impl<'a, Up: ServiceUpstream, Down: ServiceDownstream> ServiceConnection<'a, Up, Down> {
+    pub fn open(link: &'a dyn ServiceLink, service_name: &str) -> Result<Self, DbDataError> {
+        let connection_id = link.open_connection(service_name)?;
+
+        Ok(Self {
+            link,
+            connection_id,
+            request_id: 1,
+            _messages: PhantomData,
+        })
+    }
+
+    fn next_request_id(&mut self) -> u32 {
+        let id = self.request_id;
+        self.request_id += 1;
+        id
+    }
+
+    /// Sends `req` with a fresh request id and waits for its response.
+    pub fn request(&mut self, mut req: Up::Req) -> Result<Down::Rsp, DbDataError> {
+        let request_id = self.next_request_id();
+        req.set_request_id(request_id);
+
+        let data = Up::from_request(req).encode_to_vec();
+        let mut response_data = self.link.send_service_data(self.connection_id, &data)?;
+
+        loop {
+            match Down::decode(response_data.as_slice())?.into_response() {
+                Some(rsp) if rsp.request_id() == request_id => {
+                    rsp.check()?;
+                    return Ok(rsp);
+                }
+                Some(rsp) => log::debug!(
+                    "Skipping response to request {} while waiting for {}",
+                    rsp.request_id(),
+                    request_id
+                ),
+                None => log::debug!("Skipping service push while waiting for {}", request_id),
+            }
+            response_data = self.link.receive_service_data(self.connection_id)?;
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use crate::error::DbDataError;
+
+use super::{ServiceConnection, ServiceResponse, service_messages};
+use crate::auth::ServiceLink;
+use crate::proto::denuvo::{
+    Downstream, GetGameTimeTokenReq, GetGameTokenReq, GetOwnershipListTokenReq, Req, Rsp, Upstream,
+    rsp::Result as DenuvoResult,
+};
+
+service_messages!(crate::proto::denuvo);
+
+impl ServiceResponse for Rsp {
+    fn request_id(&self) -> u32 {
+        self.request_id
+    }
+
+    fn check(&self) -> Result<(), DbDataError> {
+        if self.result != DenuvoResult::Success as i32 {
+            return Err(DbDataError::from_denuvo_result(self.result));
+        }
+        Ok(())
+    }
+}
+
+pub struct DenuvoConnection<'a> {
+    connection: ServiceConnection<'a, Upstream, Downstream>,
+}
+
+impl<'a> DenuvoConnection<'a> {
+    pub fn new(socket: &'a dyn ServiceLink) -> Result<Self, DbDataError> {
+        Ok(Self {
+            connection: ServiceConnection::open(socket, "denuvo_service")?,
+        })
+    }
+
+    pub fn get_game_token(
+        &mut self,
+        ownership_token: &str,
+        request_token: &str,
+    ) -> Result<String, DbDataError> {
+        log::info!("Requesting game token from denuvo service");
+
+        let req = Req {
+            get_game_token_req: Some(GetGameTokenReq {
+                ownership_token: ownership_token.to_string(),
+                request_token: request_token.as_bytes().to_vec(),
+            }),
+            ..Default::default()
+        };
+
+        let rsp = self.connection.request(req)?;
+
+        if let Some(token_rsp) = rsp.get_game_token_rsp {
+            let token = String::from_utf8(token_rsp.game_token)
+                .map_err(|_| DbDataError::Protocol("Invalid UTF-8 in game token".to_string()))?;
+
+            log::info!("Got game token successfully");
+            return Ok(token);
+        }
+
+        Err(DbDataError::Protocol(
+            "Unexpected response to get game token request".to_string(),
+        ))
+    }
+
+    /// Asks for the token of a game held as a time trial, which is only
+    /// valid for the returned number of seconds.
+    pub fn get_game_time_token(
+        &mut self,
+        ownership_token: &str,
+        request_token: &str,
+    ) -> Result<(String, u32), DbDataError> {
+        log::info!("Requesting time trial token from denuvo service");
+
+        let req = Req {
+            get_game_time_token_req: Some(GetGameTimeTokenReq {
+                ownership_token: ownership_token.to_string(),
+                request_token: request_token.as_bytes().to_vec(),
+            }),
+            ..Default::default()
+        };
+
+        let rsp = self.connection.request(req)?;
+
+        if let Some(time_rsp) = rsp.get_game_time_token_rsp {
+            let token = String::from_utf8(time_rsp.time_token)
+                .map_err(|_| DbDataError::Protocol("Invalid UTF-8 in time token".to_string()))?;
+
+            log::info!(
+                "Got time trial token, valid for {}s",
+                time_rsp.time_token_ttl_sec
+            );
+            return Ok((token, time_rsp.time_token_ttl_sec));
+        }
+
+        Err(DbDataError::Protocol(
+            "Unexpected response to get game time token request".to_string(),
+        ))
+    }
+
+    pub fn get_ownership_list_token(
+        &mut self,
+        product_id: u32,
+        game_token: &str,
+        dlcs: Vec<u32>,
+    ) -> Result<String, DbDataError> {
+        log::info!(
+            "Requesting ownership list token for product: {} with {} DLCs",
+            product_id,
+            dlcs.len()
+        );
+
+        let req = Req {
+            get_ownership_list_token_req: Some(GetOwnershipListTokenReq {
+                product_id,
+                game_token: game_token.as_bytes().to_vec(),
+                addons_to_validate: dlcs,
+            }),
+            ..Default::default()
+        };
+
+        let rsp = self.connection.request(req)?;
+
+        if let Some(list_rsp) = rsp.get_ownership_list_token_rsp {
+            let token = String::from_utf8(list_rsp.ownership_list_token).map_err(|_| {
+                DbDataError::Protocol("Invalid UTF-8 in ownership list token".to_string())
+            })?;
+
+            log::info!("Got ownership list token successfully");
+            return Ok(token);
+        }
+
+        Err(DbDataError::Protocol(
+            "Unexpected response to get ownership list token request".to_string(),
+        ))
+    }
+}
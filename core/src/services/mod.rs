@@ -1,5 +1,7 @@
-mod ownership;
+mod connection;
 mod denuvo;
+mod ownership;
 
-pub use ownership::*;
+pub use connection::*;
 pub use denuvo::*;
+pub use ownership::*;
@@ -0,0 +1,117 @@
+#![allow(deprecated)]
+
+use crate::error::DbDataError;
+
+use super::{ServiceConnection, ServiceResponse, service_messages};
+use crate::auth::ServiceLink;
+use crate::proto::ownership::{
+    Downstream, InitializeReq, OwnedGame, OwnershipTokenReq, Req, Rsp, Upstream,
+};
+
+service_messages!(crate::proto::ownership);
+
+impl ServiceResponse for Rsp {
+    fn request_id(&self) -> u32 {
+        self.request_id
+    }
+
+    fn check(&self) -> Result<(), DbDataError> {
+        if self.initialize_rsp.as_ref().is_some_and(|rsp| !rsp.success) {
+            return Err(DbDataError::OwnershipRejected("initialize".to_string()));
+        }
+        if self
+            .ownership_token_rsp
+            .as_ref()
+            .is_some_and(|rsp| rsp.success == Some(false))
+        {
+            return Err(DbDataError::OwnershipRejected(
+                "ownership token".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+pub struct OwnershipConnection<'a> {
+    connection: ServiceConnection<'a, Upstream, Downstream>,
+    ticket: String,
+    session_id: String,
+}
+
+impl<'a> OwnershipConnection<'a> {
+    pub fn new(
+        socket: &'a dyn ServiceLink,
+        ticket: String,
+        session_id: String,
+    ) -> Result<Self, DbDataError> {
+        Ok(Self {
+            connection: ServiceConnection::open(socket, "ownership_service")?,
+            ticket,
+            session_id,
+        })
+    }
+
+    /// A request carrying the ubiservices session, as every ownership call
+    /// needs.
+    fn req(&self) -> Req {
+        Req {
+            ubi_ticket: Some(self.ticket.clone()),
+            ubi_session_id: Some(self.session_id.clone()),
+            ..Default::default()
+        }
+    }
+
+    pub fn get_owned_games(&mut self) -> Result<Vec<OwnedGame>, DbDataError> {
+        log::info!("Initializing ownership service and getting owned games");
+
+        let req = Req {
+            initialize_req: Some(InitializeReq {
+                get_associations: Some(true),
+                proto_version: Some(7),
+                use_staging: Some(false),
+                ..Default::default()
+            }),
+            ..self.req()
+        };
+
+        let rsp = self.connection.request(req)?;
+
+        if let Some(init_rsp) = rsp.initialize_rsp {
+            let games = init_rsp
+                .owned_games
+                .map(|og| og.owned_games)
+                .unwrap_or_default();
+
+            log::info!("Found {} owned games", games.len());
+            return Ok(games);
+        }
+
+        Err(DbDataError::Protocol(
+            "Unexpected response to initialize request".to_string(),
+        ))
+    }
+
+    pub fn get_ownership_token(&mut self, product_id: u32) -> Result<(String, u64), DbDataError> {
+        log::info!("Requesting ownership token for product: {}", product_id);
+
+        let req = Req {
+            ownership_token_req: Some(OwnershipTokenReq {
+                product_id: Some(product_id),
+            }),
+            ..self.req()
+        };
+
+        let rsp = self.connection.request(req)?;
+
+        if let Some(token_rsp) = rsp.ownership_token_rsp {
+            let token = token_rsp.token.unwrap_or_default();
+            let expiration = token_rsp.expiration.unwrap_or(0);
+            log::info!("Got ownership token, expires at: {}", expiration);
+            return Ok((token, expiration));
+        }
+
+        Err(DbDataError::Protocol(
+            "Unexpected response to ownership token request".to_string(),
+        ))
+    }
+}
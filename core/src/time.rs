@@ -0,0 +1,111 @@
+//! Parsing of the timestamps ubiservices sends, without pulling in a date
+//! crate, and the clock skew between us and the server.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Parses an RFC 3339 UTC timestamp such as `2024-05-01T12:34:56.1234567Z`.
+pub fn parse_rfc3339(value: &str) -> Option<SystemTime> {
+    let value = value.trim().strip_suffix('Z')?;
+    let (date, time) = value.split_once('T')?;
+
+    let mut date = date.splitn(3, '-');
+    let year = date.next()?.parse().ok()?;
+    let month = date.next()?.parse().ok()?;
+    let day = date.next()?.parse().ok()?;
+
+    let (time, fraction) = time.split_once('.').unwrap_or((time, ""));
+    let mut time = time.splitn(3, ':');
+    let hour = time.next()?.parse().ok()?;
+    let minute = time.next()?.parse().ok()?;
+    let second = time.next()?.parse().ok()?;
+
+    let nanos = if fraction.is_empty() {
+        0
+    } else if !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    } else {
+        let digits = &fraction[..fraction.len().min(9)];
+        let value: u32 = digits.parse().ok()?;
+        value * 10u32.pow(9 - digits.len() as u32)
+    };
+
+    to_system_time(year, month, day, hour, minute, second)
+        .and_then(|time| time.checked_add(Duration::from_nanos(nanos as u64)))
+}
+
+/// Parses an HTTP `Date` header in the IMF-fixdate form, such as
+/// `Sun, 06 Nov 1994 08:49:37 GMT`.
+pub fn parse_http_date(value: &str) -> Option<SystemTime> {
+    let (_weekday, rest) = value.trim().split_once(", ")?;
+    let mut parts = rest.split(' ');
+    let day = parts.next()?.parse().ok()?;
+    let month = parts.next()?;
+    let month = MONTHS.iter().position(|m| m.eq_ignore_ascii_case(month))? as u32 + 1;
+    let year = parts.next()?.parse().ok()?;
+    let time = parts.next()?;
+    if parts.next()? != "GMT" || parts.next().is_some() {
+        return None;
+    }
+
+    let mut time = time.splitn(3, ':');
+    let hour = time.next()?.parse().ok()?;
+    let minute = time.next()?.parse().ok()?;
+    let second = time.next()?.parse().ok()?;
+
+    to_system_time(year, month, day, hour, minute, second)
+}
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Whole seconds `server` is ahead of `local`, negative when it is behind.
+pub fn clock_skew(server: SystemTime, local: SystemTime) -> i64 {
+    match server.duration_since(local) {
+        Ok(ahead) => ahead.as_secs() as i64,
+        Err(behind) => -(behind.duration().as_secs() as i64),
+    }
+}
+
+/// `time` moved by `skew` seconds, as [`clock_skew`] returns them, or
+/// `None` if that is out of range.
+pub fn apply_skew(time: SystemTime, skew: i64) -> Option<SystemTime> {
+    if skew >= 0 {
+        time.checked_add(Duration::from_secs(skew as u64))
+    } else {
+        time.checked_sub(Duration::from_secs(skew.unsigned_abs()))
+    }
+}
+
+/// Seconds since the epoch for a UTC calendar date and time.
+pub(crate) fn to_system_time(
+    year: i64,
+    month: u32,
+    day: u32,
+    hour: u64,
+    minute: u64,
+    second: u64,
+) -> Option<SystemTime> {
+    if !(1..=9999).contains(&year) || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    if hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+
+    let days = days_from_civil(year, month, day);
+    let seconds = days * 86_400 + (hour * 3600 + minute * 60 + second) as i64;
+    UNIX_EPOCH.checked_add(Duration::from_secs(u64::try_from(seconds).ok()?))
+}
+
+/// Days since 1970-01-01 in the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year =
+        (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
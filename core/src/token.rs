@@ -0,0 +1,288 @@
+use std::{
+    error::Error,
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::remember_device::sha256_hex;
+use crate::secret;
+use crate::time;
+
+#[derive(Debug, Clone)]
+pub struct Token {
+    pub token: String,
+    pub ownership: Option<String>,
+    /// When the token was handed out, by the server's clock.
+    pub issued_at: Option<SystemTime>,
+    /// When the token stops being accepted, by the server's clock. Tokens
+    /// saved by older versions have no expiry and never count as expiring.
+    pub expires_at: Option<SystemTime>,
+    /// Seconds the server's clock was ahead of ours when it was issued.
+    pub clock_skew: i64,
+    /// What the token was issued for, from [`fingerprint`]. Tokens pasted
+    /// in by hand have none.
+    pub fingerprint: Option<String>,
+}
+
+/// Identifies what a token was issued for: the app id, the executable
+/// asking for it and the request token the game passed in. A game patch or
+/// hardware change alters the request token, so the fingerprint changes
+/// with it.
+pub fn fingerprint(app_id: u32, executable: &str, request_token: &str) -> String {
+    sha256_hex(
+        format!(
+            "{}\n{}\n{}",
+            app_id,
+            executable.to_lowercase(),
+            request_token
+        )
+        .as_bytes(),
+    )
+}
+
+/// The section holding the tokens of `app_id`.
+pub fn app_section(app_id: u32) -> String {
+    format!("token.{}", app_id)
+}
+
+/// `[token.<app_id>]` when the app has its own entry, otherwise the shared
+/// `[token]` section written by hand or by older versions.
+fn token_section(ini: &ini::Ini, app_id: u32) -> String {
+    let section = app_section(app_id);
+    if ini.section(Some(section.as_str())).is_some() {
+        section
+    } else {
+        "token".to_string()
+    }
+}
+
+impl Token {
+    /// Loads the cached token for `app_id`.
+    pub fn new(base: &Path, app_id: u32) -> Result<Self, Box<dyn Error>> {
+        let store = secret::default_store(base)?;
+        let ini = secret::load_ini(base, store.as_ref())?;
+        Self::from_ini(&ini, app_id, store.as_ref())
+    }
+
+    fn from_ini(
+        ini: &ini::Ini,
+        app_id: u32,
+        store: &dyn secret::SecretStore,
+    ) -> Result<Self, Box<dyn Error>> {
+        let section = token_section(ini, app_id);
+
+        let token = secret::get(ini, &section, "token", store)?
+            .ok_or("Token not found in dbdata.ini file")?;
+
+        if token.is_empty() {
+            return Err("Token is empty in dbdata.ini".into());
+        }
+
+        let number = |key: &str| {
+            ini.section(Some(section.as_str()))
+                .and_then(|sec| sec.get(key))
+                .and_then(|v| v.trim().parse::<i64>().ok())
+        };
+        let timestamp = |key: &str| {
+            number(key)
+                .and_then(|secs| u64::try_from(secs).ok())
+                .and_then(|secs| UNIX_EPOCH.checked_add(Duration::from_secs(secs)))
+        };
+
+        Ok(Self {
+            token,
+            ownership: secret::get(ini, &section, "ownership", store)?.filter(|s| !s.is_empty()),
+            issued_at: timestamp("issued_at"),
+            expires_at: timestamp("expires_at"),
+            clock_skew: number("clock_skew").unwrap_or(0),
+            fingerprint: ini
+                .section(Some(section.as_str()))
+                .and_then(|sec| sec.get("fingerprint"))
+                .filter(|v| !v.is_empty())
+                .map(|v| v.to_string()),
+        })
+    }
+
+    pub fn from_values(token: String, ownership: Option<String>) -> Self {
+        Self {
+            token,
+            ownership,
+            issued_at: None,
+            expires_at: None,
+            clock_skew: 0,
+            fingerprint: None,
+        }
+    }
+
+    /// Sets when the token was issued and expires, both by the server's
+    /// clock, and how far that clock is ahead of ours.
+    pub fn with_lifetime(
+        mut self,
+        issued_at: SystemTime,
+        expires_at: Option<SystemTime>,
+        clock_skew: i64,
+    ) -> Self {
+        self.issued_at = Some(issued_at);
+        self.expires_at = expires_at;
+        self.clock_skew = clock_skew;
+        self
+    }
+
+    pub fn with_fingerprint(mut self, fingerprint: String) -> Self {
+        self.fingerprint = Some(fingerprint);
+        self
+    }
+
+    /// Whether the token was issued for `fingerprint`. Tokens without one
+    /// are trusted, since they were put there by hand.
+    pub fn matches_fingerprint(&self, fingerprint: &str) -> bool {
+        self.fingerprint
+            .as_deref()
+            .is_none_or(|own| own == fingerprint)
+    }
+
+    /// Whether the token expires within `margin` from now, by the server's
+    /// clock.
+    pub fn expires_within(&self, margin: Duration) -> bool {
+        let Some(expires_at) = self.expires_at else {
+            return false;
+        };
+        match time::apply_skew(SystemTime::now(), self.clock_skew)
+            .and_then(|now| now.checked_add(margin))
+        {
+            Some(limit) => expires_at <= limit,
+            // Only a hand-edited skew gets here; fetch a fresh token.
+            None => true,
+        }
+    }
+
+    /// How long before expiry a new token should be fetched: `margin`, or
+    /// half the token's lifetime when that is shorter, as it is for the
+    /// time tokens of a trial.
+    pub fn refresh_margin(&self, margin: Duration) -> Duration {
+        match (self.issued_at, self.expires_at) {
+            (Some(issued_at), Some(expires_at)) => expires_at
+                .duration_since(issued_at)
+                .map_or(margin, |lifetime| margin.min(lifetime / 2)),
+            _ => margin,
+        }
+    }
+
+    /// When a new token should be fetched by our clock, for scheduling a
+    /// refresh while the game runs. `None` when it never expires.
+    pub fn refresh_at(&self, margin: Duration) -> Option<SystemTime> {
+        let expires_at = time::apply_skew(self.expires_at?, self.clock_skew.checked_neg()?)?;
+        Some(
+            expires_at
+                .checked_sub(self.refresh_margin(margin))
+                .unwrap_or(expires_at),
+        )
+    }
+
+    /// Saves the token and owned DLCs in the `[token.<app_id>]` section,
+    /// leaving other apps' entries alone.
+    pub fn save_with_dlcs(
+        &self,
+        base: &Path,
+        app_id: u32,
+        dlcs: &[u32],
+    ) -> Result<(), Box<dyn Error>> {
+        let ini_path = base.join("dbdata.ini");
+        let store = secret::default_store(base)?;
+        let section = app_section(app_id);
+
+        let mut ini = ini::Ini::load_from_file(&ini_path).unwrap_or_else(|_| ini::Ini::new());
+
+        let ownership = self.ownership.as_deref().unwrap_or_default();
+
+        let unix = |time: Option<SystemTime>| {
+            time.and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map(|since| since.as_secs().to_string())
+                .unwrap_or_default()
+        };
+        let dlcs_str = dlcs
+            .iter()
+            .map(|d| d.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+
+        ini.with_section(Some(section.as_str()))
+            .set("token", secret::seal(store.as_ref(), &self.token)?)
+            .set("ownership", secret::seal(store.as_ref(), ownership)?)
+            .set("issued_at", unix(self.issued_at))
+            .set("expires_at", unix(self.expires_at))
+            .set("clock_skew", self.clock_skew.to_string())
+            .set(
+                "fingerprint",
+                self.fingerprint.as_deref().unwrap_or_default(),
+            )
+            .set("dlcs", dlcs_str);
+
+        ini.write_to_file(&ini_path)?;
+        log::info!(
+            "Saved tokens and {} DLCs for app {} to dbdata.ini",
+            dlcs.len(),
+            app_id
+        );
+
+        Ok(())
+    }
+
+    /// Forgets the cached token for `app_id` after the game rejected it:
+    /// removes its `[token.<app_id>]` section, or empties the shared
+    /// `[token]` section it was read from.
+    pub fn invalidate(base: &Path, app_id: u32) -> Result<(), Box<dyn Error>> {
+        let ini_path = base.join("dbdata.ini");
+        let mut ini = ini::Ini::load_from_file(&ini_path)?;
+
+        let section = token_section(&ini, app_id);
+        if section == "token" {
+            ini.with_section(Some("token"))
+                .set("token", "")
+                .set("ownership", "");
+        } else {
+            ini.delete(Some(section.as_str()));
+        }
+
+        ini.write_to_file(&ini_path)?;
+        log::info!(
+            "Removed the cached token for app {} from dbdata.ini",
+            app_id
+        );
+
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct Settings {
+    pub dlcs: Vec<u32>,
+    pub token: Token,
+}
+
+impl Settings {
+    /// Loads the cached token and owned DLCs for `app_id`.
+    pub fn new(base: &Path, app_id: u32) -> Result<Self, Box<dyn Error>> {
+        let store = secret::default_store(base)?;
+        let ini = secret::load_ini(base, store.as_ref())?;
+        let token = Token::from_ini(&ini, app_id, store.as_ref())?;
+
+        let section = app_section(app_id);
+        let dlcs = match ini.section(Some(section.as_str())) {
+            Some(app) => app.get("dlcs"),
+            None => ini
+                .section(Some("settings"))
+                .and_then(|sec| sec.get("dlcs")),
+        };
+
+        let dlcs = dlcs
+            .map(|s| {
+                s.split(',')
+                    .filter_map(|d| d.trim().parse::<u32>().ok())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Ok(Self { dlcs, token })
+    }
+}
@@ -0,0 +1,153 @@
+//! Imports the remember-me ticket from the Ubisoft Connect client's own
+//! login cache, in the `Cache` format of `user_dat_file.proto`.
+//!
+//! Only read when `[user_cache] path` is set in `dbdata.ini`.
+
+use std::path::Path;
+
+use prost::Message;
+
+use crate::error::DbDataError;
+use crate::proto::user_dat_file::{
+    Cache, EnvironmentCache, LegacyVulnerableUnversionedCache, UserInfo,
+    cache::Prod,
+    environment_cache::StartupEntry,
+    startup_entry::UserIndex,
+    user_info::{Email, Name, RememberMeTicket, UbiProfileId, Username},
+};
+
+/// An account the client remembers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachedAccount {
+    pub username: Option<String>,
+    pub email: Option<String>,
+    pub name: Option<String>,
+    pub profile_id: Option<String>,
+    pub remember_me_ticket: Option<String>,
+}
+
+impl CachedAccount {
+    fn from_user(user: UserInfo) -> Self {
+        Self {
+            username: user.username.map(|Username::Username(v)| v),
+            email: user.email.map(|Email::Email(v)| v),
+            name: user.name.map(|Name::Name(v)| v),
+            profile_id: user.ubi_profile_id.map(|UbiProfileId::UbiProfileId(v)| v),
+            remember_me_ticket: user
+                .remember_me_ticket
+                .map(|RememberMeTicket::RememberMeTicket(v)| v)
+                .filter(|v| !v.is_empty()),
+        }
+    }
+
+    /// The name shown when asking which account to use.
+    pub fn label(&self) -> &str {
+        self.email
+            .as_deref()
+            .or(self.username.as_deref())
+            .or(self.name.as_deref())
+            .or(self.profile_id.as_deref())
+            .unwrap_or("<unnamed>")
+    }
+
+    /// Whether `account` names this account by email, username, display
+    /// name or profile id.
+    pub fn matches(&self, account: &str) -> bool {
+        [&self.email, &self.username, &self.name, &self.profile_id]
+            .into_iter()
+            .flatten()
+            .any(|value| value.eq_ignore_ascii_case(account.trim()))
+    }
+}
+
+/// The production accounts from a client login cache.
+#[derive(Debug, Clone)]
+pub struct UserCache {
+    accounts: Vec<CachedAccount>,
+    /// The account the client logs in with at startup.
+    startup_index: Option<usize>,
+}
+
+impl UserCache {
+    pub fn load(path: &Path) -> Result<Self, DbDataError> {
+        let data = std::fs::read(path)?;
+        Self::decode(&data)
+    }
+
+    /// Reads the versioned `Cache`, falling back to the older format that
+    /// is just a list of users.
+    pub fn decode(data: &[u8]) -> Result<Self, DbDataError> {
+        if let Ok(Cache {
+            prod: Some(Prod::Prod(prod)),
+            ..
+        }) = Cache::decode(data)
+            && !prod.users.is_empty()
+        {
+            return Ok(Self::from_environment(prod));
+        }
+
+        let legacy = LegacyVulnerableUnversionedCache::decode(data).map_err(|e| {
+            DbDataError::UserCache(format!("not a Ubisoft Connect login cache: {}", e))
+        })?;
+        Ok(Self {
+            accounts: legacy
+                .users
+                .into_iter()
+                .map(CachedAccount::from_user)
+                .collect(),
+            startup_index: None,
+        })
+    }
+
+    fn from_environment(environment: EnvironmentCache) -> Self {
+        let startup_index = environment
+            .startup_entry
+            .and_then(|StartupEntry::StartupEntry(entry)| entry.user_index)
+            .map(|UserIndex::UserIndex(index)| index as usize);
+
+        Self {
+            accounts: environment
+                .users
+                .into_iter()
+                .map(CachedAccount::from_user)
+                .collect(),
+            startup_index,
+        }
+    }
+
+    pub fn accounts(&self) -> &[CachedAccount] {
+        &self.accounts
+    }
+
+    /// Picks the account named by `account`. Without a name, the only
+    /// account with a remember-me ticket is used, then the one the client
+    /// starts with.
+    pub fn select(&self, account: Option<&str>) -> Result<&CachedAccount, DbDataError> {
+        let with_ticket: Vec<_> = self
+            .accounts
+            .iter()
+            .filter(|a| a.remember_me_ticket.is_some())
+            .collect();
+
+        let selected = match account.filter(|a| !a.trim().is_empty()) {
+            Some(account) => with_ticket.iter().find(|a| a.matches(account)).copied(),
+            None if with_ticket.len() == 1 => Some(with_ticket[0]),
+            None => self
+                .startup_index
+                .and_then(|index| self.accounts.get(index))
+                .filter(|a| a.remember_me_ticket.is_some()),
+        };
+
+        selected.ok_or_else(|| {
+            if with_ticket.is_empty() {
+                return DbDataError::UserCache("no account has a saved login".to_string());
+            }
+            let labels = with_ticket
+                .iter()
+                .map(|a| a.label())
+                .collect::<Vec<_>>()
+                .join(", ");
+            DbDataError::UserCache(format!("set account in [user_cache] to one of: {}", labels))
+        })
+    }
+}
@@ -0,0 +1,65 @@
+use std::ffi::CStr;
+
+use dbdata_core::buffers::Buffers;
+
+#[test]
+fn unchanged_tokens_keep_their_buffer() {
+    let mut buffers = Buffers::default();
+
+    let (first, len) = buffers.token("game-token");
+    assert_eq!(len, "game-token".len());
+    let (second, _) = buffers.token("game-token");
+    assert_eq!(first, second);
+    assert_eq!(
+        unsafe { CStr::from_ptr(second) }.to_str().unwrap(),
+        "game-token"
+    );
+
+    let (first, _) = buffers.dlcs(&[1, 2, 3]);
+    let (second, len) = buffers.dlcs(&[1, 2, 3]);
+    assert_eq!(first, second);
+    assert_eq!(len, 3);
+}
+
+#[test]
+fn changed_tokens_get_a_new_buffer() {
+    let mut buffers = Buffers::default();
+
+    buffers.token("old-token");
+    let (ptr, len) = buffers.token("new-token");
+    assert_eq!(len, "new-token".len());
+    assert_eq!(
+        unsafe { CStr::from_ptr(ptr) }.to_str().unwrap(),
+        "new-token"
+    );
+
+    buffers.dlcs(&[1]);
+    let (ptr, len) = buffers.dlcs(&[4, 5]);
+    assert_eq!(unsafe { std::slice::from_raw_parts(ptr, len) }, &[4, 5]);
+}
+
+#[test]
+fn invalidated_tokens_give_an_empty_buffer() {
+    let mut buffers = Buffers::default();
+
+    buffers.ownership("ownership-token");
+    let (ptr, len) = buffers.ownership("");
+    assert_eq!(len, 0);
+    assert!(!ptr.is_null());
+    assert_eq!(unsafe { CStr::from_ptr(ptr) }.to_bytes(), b"");
+
+    let (_, len) = buffers.dlcs(&[]);
+    assert_eq!(len, 0);
+}
+
+#[test]
+fn nuls_do_not_cut_the_token_short() {
+    let mut buffers = Buffers::default();
+
+    let (ptr, len) = buffers.token("game\0-token");
+    assert_eq!(len, "game-token".len());
+    assert_eq!(
+        unsafe { CStr::from_ptr(ptr) }.to_str().unwrap(),
+        "game-token"
+    );
+}
@@ -0,0 +1,73 @@
+use std::time::Duration;
+
+use dbdata_core::config::{DbDataConfig, NetworkConfig};
+
+fn load(content: &str) -> Result<DbDataConfig, String> {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("dbdata.ini"), content).unwrap();
+    DbDataConfig::load(dir.path()).map_err(|e| e.to_string())
+}
+
+#[test]
+fn missing_network_section_keeps_defaults() {
+    let config = load("[Uplay]\nemail=a@b.c\npassword=x\n").unwrap();
+    assert_eq!(config.network, NetworkConfig::default());
+    assert_eq!(config.network.demux_host, "dmx.upc.ubisoft.com");
+    assert!(config.network.login_tls);
+}
+
+#[test]
+fn network_section_overrides_endpoints_and_timeouts() {
+    let config = load(
+        "[Uplay]\nemail=\npassword=\n\
+         [network]\n\
+         demux_host=127.0.0.1\ndemux_port=7777\ndemux_tls=false\n\
+         login_host=localhost\nlogin_port=7778\nlogin_path=/sessions\nlogin_tls=false\n\
+         connect_timeout=5\nread_timeout=10\n",
+    )
+    .unwrap();
+
+    let network = config.network;
+    assert_eq!(network.demux_host, "127.0.0.1");
+    assert_eq!(network.demux_port, 7777);
+    assert!(!network.demux_tls);
+    assert_eq!(network.connect_timeout, Duration::from_secs(5));
+    assert_eq!(network.read_timeout, Duration::from_secs(10));
+
+    let endpoint = network.login_endpoint();
+    assert_eq!(endpoint.host, "localhost");
+    assert_eq!(endpoint.port, 7778);
+    assert_eq!(endpoint.path, "/sessions");
+    assert!(!endpoint.tls);
+}
+
+#[test]
+fn empty_network_values_keep_defaults() {
+    let config =
+        load("[Uplay]\nemail=\npassword=\n[network]\ndemux_host=\nread_timeout=\n").unwrap();
+    assert_eq!(config.network, NetworkConfig::default());
+}
+
+#[test]
+fn invalid_network_values_are_rejected() {
+    let err = load("[Uplay]\n[network]\ndemux_port=https\n").unwrap_err();
+    assert!(err.contains("demux_port"), "{}", err);
+
+    assert!(load("[Uplay]\n[network]\nread_timeout=0\n").is_err());
+}
+
+#[test]
+fn ca_bundle_is_resolved_next_to_the_ini() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(
+        dir.path().join("dbdata.ini"),
+        "[Uplay]\n[network]\nca_bundle=proxy.pem\n",
+    )
+    .unwrap();
+
+    let config = DbDataConfig::load(dir.path()).unwrap();
+    assert_eq!(config.network.ca_bundle, Some(dir.path().join("proxy.pem")));
+
+    // The bundle does not exist, so building the trust roots must fail loudly.
+    assert!(config.network.connect_options().is_err());
+}
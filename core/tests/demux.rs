@@ -0,0 +1,271 @@
+use std::thread;
+
+use dbdata_core::auth::transport::{MemoryStream, duplex};
+use dbdata_core::auth::{DemuxSocket, read_frame, write_frame};
+use dbdata_core::error::DbDataError;
+use dbdata_core::proto::demux::{
+    AuthenticateRsp, ConnectionClosedPush, DataMessage, Downstream, GetPatchInfoRsp, KeepAlivePush,
+    OpenConnectionRsp, Push, Rsp, Upstream, connection_closed_push::ConnectionErrorCode,
+};
+use prost::Message;
+
+fn recv(stream: &mut MemoryStream) -> Upstream {
+    Upstream::decode(read_frame(stream).unwrap().as_slice()).unwrap()
+}
+
+fn send(stream: &mut MemoryStream, downstream: Downstream) {
+    write_frame(stream, &downstream.encode_to_vec()).unwrap();
+}
+
+fn response(rsp: Rsp) -> Downstream {
+    Downstream {
+        response: Some(rsp),
+        push: None,
+    }
+}
+
+fn push(push: Push) -> Downstream {
+    Downstream {
+        response: None,
+        push: Some(push),
+    }
+}
+
+fn data_push(connection_id: u32, payload: &[u8]) -> Downstream {
+    let mut data = (payload.len() as u32).to_be_bytes().to_vec();
+    data.extend_from_slice(payload);
+    push(Push {
+        data: Some(DataMessage {
+            connection_id,
+            data,
+        }),
+        ..Default::default()
+    })
+}
+
+#[test]
+fn push_version_queries_patch_info_first() {
+    let (client, mut server) = duplex();
+    let socket = DemuxSocket::from_transport(client).unwrap();
+
+    let server = thread::spawn(move || {
+        let up = recv(&mut server);
+        let req = up.request.unwrap();
+        assert!(req.get_patch_info_req.is_some());
+        send(
+            &mut server,
+            response(Rsp {
+                request_id: req.request_id,
+                get_patch_info_rsp: Some(GetPatchInfoRsp {
+                    success: true,
+                    latest_version: 11200,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+        );
+
+        let up = recv(&mut server);
+        up.push.unwrap().client_version.unwrap().version
+    });
+
+    socket.push_version().unwrap();
+    assert_eq!(server.join().unwrap(), 11200);
+}
+
+#[test]
+fn authenticate_and_open_connection() {
+    let (client, mut server) = duplex();
+    let socket = DemuxSocket::from_transport(client).unwrap();
+
+    let server = thread::spawn(move || {
+        let req = recv(&mut server).request.unwrap();
+        let auth = req.authenticate_req.unwrap();
+        assert_eq!(auth.token.ubi_ticket.as_deref(), Some("ticket"));
+        send(
+            &mut server,
+            response(Rsp {
+                request_id: req.request_id,
+                authenticate_rsp: Some(AuthenticateRsp {
+                    success: true,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+        );
+
+        let req = recv(&mut server).request.unwrap();
+        let open = req.open_connection_req.unwrap();
+        assert_eq!(open.service_name, "ownership_service");
+        send(
+            &mut server,
+            response(Rsp {
+                request_id: req.request_id,
+                open_connection_rsp: Some(OpenConnectionRsp {
+                    success: true,
+                    connection_id: 7,
+                }),
+                ..Default::default()
+            }),
+        );
+    });
+
+    socket.authenticate("ticket", true).unwrap();
+    assert_eq!(socket.open_connection("ownership_service").unwrap(), 7);
+    server.join().unwrap();
+}
+
+#[test]
+fn service_data_answers_keep_alive_and_skips_other_connections() {
+    let (client, mut server) = duplex();
+    let socket = DemuxSocket::from_transport(client).unwrap();
+
+    let server = thread::spawn(move || {
+        let data = recv(&mut server).push.unwrap().data.unwrap();
+        assert_eq!(data.connection_id, 3);
+        assert_eq!(&data.data[4..], b"ping");
+
+        send(
+            &mut server,
+            push(Push {
+                keep_alive: Some(KeepAlivePush {}),
+                ..Default::default()
+            }),
+        );
+        assert!(recv(&mut server).push.unwrap().keep_alive.is_some());
+
+        send(&mut server, data_push(9, b"other"));
+        send(&mut server, data_push(3, b"pong"));
+    });
+
+    assert_eq!(socket.send_service_data(3, b"ping").unwrap(), b"pong");
+    server.join().unwrap();
+}
+
+#[test]
+fn disconnect_ends_the_stream() {
+    let (client, mut server) = duplex();
+    let socket = DemuxSocket::from_transport(client).unwrap();
+
+    socket.disconnect();
+    assert!(read_frame(&mut server).is_err());
+}
+
+#[test]
+fn responses_are_matched_by_request_id() {
+    let (client, mut server) = duplex();
+    let socket = DemuxSocket::from_transport(client).unwrap();
+
+    let server = thread::spawn(move || {
+        let first = recv(&mut server).request.unwrap();
+        let second = recv(&mut server).request.unwrap();
+        for req in [second, first] {
+            let mut rsp = Rsp {
+                request_id: req.request_id,
+                ..Default::default()
+            };
+            if req.get_patch_info_req.is_some() {
+                rsp.get_patch_info_rsp = Some(GetPatchInfoRsp {
+                    success: true,
+                    latest_version: 11200,
+                    ..Default::default()
+                });
+            } else {
+                rsp.open_connection_rsp = Some(OpenConnectionRsp {
+                    success: true,
+                    connection_id: 5,
+                });
+            }
+            send(&mut server, response(rsp));
+        }
+    });
+
+    thread::scope(|s| {
+        let version = s.spawn(|| socket.get_latest_version().unwrap());
+        let connection = s.spawn(|| socket.open_connection("denuvo_service").unwrap());
+        assert_eq!(version.join().unwrap(), 11200);
+        assert_eq!(connection.join().unwrap(), 5);
+    });
+    server.join().unwrap();
+}
+
+#[test]
+fn service_calls_on_different_connections_overlap() {
+    let (client, mut server) = duplex();
+    let socket = DemuxSocket::from_transport(client).unwrap();
+
+    let server = thread::spawn(move || {
+        let first = recv(&mut server).push.unwrap().data.unwrap();
+        let second = recv(&mut server).push.unwrap().data.unwrap();
+        for data in [second, first] {
+            let mut reply = data.data[4..].to_vec();
+            reply.reverse();
+            send(&mut server, data_push(data.connection_id, &reply));
+        }
+    });
+
+    thread::scope(|s| {
+        let one = s.spawn(|| socket.send_service_data(1, b"abc").unwrap());
+        let two = s.spawn(|| socket.send_service_data(2, b"xyz").unwrap());
+        assert_eq!(one.join().unwrap(), b"cba");
+        assert_eq!(two.join().unwrap(), b"zyx");
+    });
+    server.join().unwrap();
+}
+
+#[test]
+fn connection_closed_fails_only_that_connection_and_reaches_subscribers() {
+    let (client, mut server) = duplex();
+    let socket = DemuxSocket::from_transport(client).unwrap();
+    let pushes = socket.subscribe();
+
+    let server = thread::spawn(move || {
+        recv(&mut server);
+        send(
+            &mut server,
+            push(Push {
+                connection_closed: Some(ConnectionClosedPush {
+                    connection_id: 1,
+                    error_code: Some(ConnectionErrorCode::ConnectionForceQuit as i32),
+                }),
+                ..Default::default()
+            }),
+        );
+        recv(&mut server);
+        send(&mut server, data_push(2, b"still here"));
+    });
+
+    let err = socket.send_service_data(1, b"one").unwrap_err();
+    assert!(
+        matches!(
+            err,
+            DbDataError::ConnectionClosed(Some(ConnectionErrorCode::ConnectionForceQuit))
+        ),
+        "{:?}",
+        err
+    );
+    assert_eq!(socket.send_service_data(2, b"two").unwrap(), b"still here");
+
+    let closed = pushes.recv().unwrap().connection_closed.unwrap();
+    assert_eq!(closed.connection_id, 1);
+    server.join().unwrap();
+}
+
+#[test]
+fn end_of_stream_fails_pending_requests() {
+    let (client, mut server) = duplex();
+    let socket = DemuxSocket::from_transport(client).unwrap();
+
+    let server = thread::spawn(move || {
+        recv(&mut server);
+    });
+
+    let err = socket.get_latest_version().unwrap_err();
+    assert!(
+        matches!(err, DbDataError::ConnectionClosed(None)),
+        "{:?}",
+        err
+    );
+    assert!(socket.is_closed());
+    server.join().unwrap();
+}
@@ -0,0 +1,77 @@
+use std::io;
+
+use dbdata_core::error::DbDataError;
+use dbdata_core::proto::demux::connection_closed_push::ConnectionErrorCode;
+use dbdata_core::proto::denuvo::rsp::Result as DenuvoResult;
+
+#[test]
+fn maps_denuvo_result_codes() {
+    assert!(matches!(
+        DbDataError::from_denuvo_result(DenuvoResult::NotOwned as i32),
+        DbDataError::NotOwned(None)
+    ));
+    assert!(matches!(
+        DbDataError::from_denuvo_result(DenuvoResult::ExceededActivations as i32),
+        DbDataError::ExceededActivations
+    ));
+    assert!(matches!(
+        DbDataError::from_denuvo_result(DenuvoResult::TimeOut as i32),
+        DbDataError::DenuvoTimeOut
+    ));
+    assert!(matches!(
+        DbDataError::from_denuvo_result(DenuvoResult::NoSessions as i32),
+        DbDataError::NoSessions
+    ));
+    assert!(matches!(
+        DbDataError::from_denuvo_result(42),
+        DbDataError::DenuvoFailure(42)
+    ));
+}
+
+#[test]
+fn separates_retryable_from_permanent_errors() {
+    assert!(DbDataError::Io(io::Error::from(io::ErrorKind::TimedOut)).is_retryable());
+    assert!(DbDataError::ConnectionClosed(None).is_retryable());
+    assert!(DbDataError::ReplyTimeout.is_retryable());
+    assert!(
+        DbDataError::DemuxAuthRejected {
+            expired: true,
+            banned: false
+        }
+        .is_retryable()
+    );
+
+    assert!(
+        !DbDataError::ConnectionClosed(Some(ConnectionErrorCode::ConnectionBanned)).is_retryable()
+    );
+    assert!(!DbDataError::NotOwned(Some(4553)).is_retryable());
+    assert!(!DbDataError::Config("bad".to_string()).is_retryable());
+    assert!(!DbDataError::MissingCredentials.is_retryable());
+}
+
+#[test]
+fn explains_errors_to_the_user() {
+    let err = DbDataError::Login {
+        status: 401,
+        error_code: Some(1),
+        message: "Invalid credentials".to_string(),
+    };
+    assert_eq!(
+        err.to_string(),
+        "Login failed with status 401 (error code 1): Invalid credentials"
+    );
+    assert!(err.explanation().contains("email or password"));
+
+    let err = DbDataError::DemuxAuthRejected {
+        expired: false,
+        banned: true,
+    };
+    assert!(err.explanation().contains("banned"));
+
+    let err = DbDataError::ConnectionClosed(Some(ConnectionErrorCode::ConnectionMultipleLogin));
+    assert!(
+        err.explanation()
+            .contains("Close Ubisoft Connect on the other PC")
+    );
+    assert!(!err.is_retryable());
+}
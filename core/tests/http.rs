@@ -0,0 +1,215 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::mpsc::{Receiver, channel};
+use std::thread;
+
+use dbdata_core::auth::transport::ConnectOptions;
+use dbdata_core::error::DbDataError;
+use dbdata_core::http::{HttpClient, Request, Url, read_response};
+
+/// Serves each canned response to one connection, in order, and reports the
+/// request heads it received.
+fn serve(responses: Vec<String>) -> (Url, Receiver<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = Url::parse(&format!("http://{}/start", listener.local_addr().unwrap())).unwrap();
+    let (tx, rx) = channel();
+
+    thread::spawn(move || {
+        for response in responses {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+            let mut head = String::new();
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if let Some(v) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                    length = v.trim().parse().unwrap();
+                }
+                head.push_str(&line);
+                if line == "\r\n" {
+                    break;
+                }
+            }
+            let mut body = vec![0u8; length];
+            reader.read_exact(&mut body).unwrap();
+            tx.send(head + &String::from_utf8(body).unwrap()).unwrap();
+
+            let mut stream = stream;
+            stream.write_all(response.as_bytes()).unwrap();
+        }
+    });
+
+    (url, rx)
+}
+
+fn client() -> HttpClient {
+    HttpClient::new(ConnectOptions::default())
+}
+
+#[test]
+fn parses_headers_into_a_map() {
+    let raw = "HTTP/1.1 200 OK\r\nContent-Length: 2\r\nX-Thing: a\r\nx-thing: b\r\n\r\nhi";
+    let response = read_response(&mut raw.as_bytes(), "GET", 1024).unwrap();
+
+    assert_eq!(response.status, 200);
+    assert_eq!(response.reason, "OK");
+    assert_eq!(response.header("X-THING"), Some("a, b"));
+    assert_eq!(response.text(), "hi");
+}
+
+#[test]
+fn decodes_chunked_bodies_with_extensions_and_trailers() {
+    let raw = "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+               5;ext=1\r\nhello\r\n6\r\n world\r\n0\r\nX-Trailer: yes\r\n\r\n";
+    let response = read_response(&mut raw.as_bytes(), "GET", 1024).unwrap();
+
+    assert_eq!(response.text(), "hello world");
+}
+
+#[test]
+fn rejects_chunk_sizes_past_the_limit_without_overflowing() {
+    let raw = "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+               5\r\nhello\r\nffffffffffffffff\r\n";
+    let err = read_response(&mut raw.as_bytes(), "GET", 1024).unwrap_err();
+
+    assert!(matches!(err, DbDataError::Protocol(_)), "{:?}", err);
+}
+
+#[test]
+fn reads_until_close_without_a_length() {
+    let raw = "HTTP/1.1 200 OK\r\n\r\nuntil the end";
+    let response = read_response(&mut raw.as_bytes(), "GET", 1024).unwrap();
+
+    assert_eq!(response.text(), "until the end");
+}
+
+#[test]
+fn no_content_has_no_body() {
+    let raw = "HTTP/1.1 204 No Content\r\n\r\n";
+    let response = read_response(&mut raw.as_bytes(), "DELETE", 1024).unwrap();
+
+    assert!(response.body.is_empty());
+}
+
+#[test]
+fn enforces_the_body_size_limit() {
+    let raw = "HTTP/1.1 200 OK\r\nContent-Length: 100\r\n\r\n";
+    assert!(read_response(&mut raw.as_bytes(), "GET", 10).is_err());
+
+    let raw = "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n20\r\n";
+    assert!(read_response(&mut raw.as_bytes(), "GET", 10).is_err());
+
+    let raw = "HTTP/1.1 200 OK\r\n\r\nmore than ten bytes";
+    assert!(read_response(&mut raw.as_bytes(), "GET", 10).is_err());
+}
+
+#[test]
+fn rejects_truncated_responses() {
+    assert!(read_response(&mut "".as_bytes(), "GET", 1024).is_err());
+    assert!(read_response(&mut "HTTP/1.1 200 OK\r\nX-".as_bytes(), "GET", 1024).is_err());
+    assert!(read_response(&mut "garbage\r\n\r\n".as_bytes(), "GET", 1024).is_err());
+
+    let raw = "HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\nshort";
+    assert!(read_response(&mut raw.as_bytes(), "GET", 1024).is_err());
+}
+
+#[test]
+fn resolves_redirect_locations() {
+    let base = Url::parse("https://example.com:8443/v3/profiles/sessions?x=1").unwrap();
+    assert_eq!(base.port, 8443);
+
+    assert_eq!(base.join("/other").unwrap().path, "/other");
+    assert_eq!(base.join("me").unwrap().path, "/v3/profiles/me");
+
+    let absolute = base.join("http://other.example/x").unwrap();
+    assert!(!absolute.tls);
+    assert_eq!(absolute.host, "other.example");
+    assert_eq!(absolute.port, 80);
+
+    let relative_scheme = base.join("//cdn.example/y").unwrap();
+    assert!(relative_scheme.tls);
+    assert_eq!(relative_scheme.host, "cdn.example");
+}
+
+#[test]
+fn follows_redirects_and_turns_post_into_get() {
+    let (url, requests) = serve(vec![
+        "HTTP/1.1 302 Found\r\nLocation: /moved\r\nContent-Length: 0\r\n\r\n".to_string(),
+        "HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\ndone".to_string(),
+    ]);
+
+    let response = client()
+        .send(Request::new("POST", url).json_body("{}".to_string()))
+        .unwrap();
+    assert_eq!(response.text(), "done");
+
+    let first = requests.recv().unwrap();
+    assert!(first.starts_with("POST /start HTTP/1.1\r\n"), "{}", first);
+    assert!(first.ends_with("{}"));
+
+    let second = requests.recv().unwrap();
+    assert!(second.starts_with("GET /moved HTTP/1.1\r\n"), "{}", second);
+    assert!(!second.contains("Content-Type"));
+}
+
+#[test]
+fn temporary_redirect_keeps_method_and_body() {
+    let (url, requests) = serve(vec![
+        "HTTP/1.1 307 Temporary Redirect\r\nLocation: /again\r\n\r\n".to_string(),
+        "HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n".to_string(),
+    ]);
+
+    client()
+        .send(Request::new("POST", url).json_body("{\"a\":1}".to_string()))
+        .unwrap();
+
+    requests.recv().unwrap();
+    let second = requests.recv().unwrap();
+    assert!(second.starts_with("POST /again HTTP/1.1\r\n"), "{}", second);
+    assert!(second.ends_with("{\"a\":1}"));
+}
+
+#[test]
+fn redirect_to_another_host_drops_credentials() {
+    let (target, requests) = serve(vec![
+        "HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n".to_string(),
+    ]);
+    let (url, first) = serve(vec![format!(
+        "HTTP/1.1 302 Found\r\nLocation: http://localhost:{}/moved\r\nContent-Length: 0\r\n\r\n",
+        target.port
+    )]);
+
+    client()
+        .send(
+            Request::new("GET", url)
+                .header("Authorization", "Ubi_v1 t=ticket")
+                .header("Ubi-SessionId", "session")
+                .header("Ubi-RememberDeviceTicket", "device")
+                .header("Ubi-AppId", "app"),
+        )
+        .unwrap();
+
+    let first = first.recv().unwrap();
+    assert!(first.contains("Ubi-SessionId: session"), "{}", first);
+
+    let second = requests.recv().unwrap();
+    assert!(second.starts_with("GET /moved HTTP/1.1\r\n"), "{}", second);
+    assert!(second.contains("Ubi-AppId: app"), "{}", second);
+    for header in ["Authorization", "Ubi-SessionId", "Ubi-RememberDeviceTicket"] {
+        assert!(!second.contains(header), "{}", second);
+    }
+}
+
+#[test]
+fn stops_after_too_many_redirects() {
+    let redirect = "HTTP/1.1 301 Moved\r\nLocation: /loop\r\nContent-Length: 0\r\n\r\n".to_string();
+    let (url, _requests) = serve(vec![redirect; 3]);
+
+    let err = client()
+        .with_max_redirects(2)
+        .send(Request::new("GET", url))
+        .unwrap_err();
+    assert!(err.to_string().contains("Too many redirects"), "{}", err);
+}
@@ -0,0 +1,110 @@
+use dbdata_core::config::DbDataConfig;
+use dbdata_core::proto::remember_device_file::{
+    User, UserLoginCache,
+    user::{AccountId, EmailHash, RdTicket},
+    user_login_cache::Version,
+};
+use dbdata_core::remember_device::{RememberDeviceStore, email_hash};
+use dbdata_core::secret::{FileKeyStore, SecretStore};
+use prost::Message;
+
+#[test]
+fn tickets_are_kept_per_account() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = RememberDeviceStore::new(dir.path());
+    assert_eq!(store.ticket("user@example.com").unwrap(), None);
+
+    store
+        .save("user@example.com", Some("account-1"), "rd-one")
+        .unwrap();
+    store.save("other@example.com", None, "rd-two").unwrap();
+    store.save("User@Example.com", None, "rd-three").unwrap();
+
+    assert_eq!(
+        store.ticket("user@example.com").unwrap().as_deref(),
+        Some("rd-three")
+    );
+    assert_eq!(
+        store.ticket("other@example.com").unwrap().as_deref(),
+        Some("rd-two")
+    );
+}
+
+#[test]
+fn file_uses_the_user_login_cache_format() {
+    let dir = tempfile::tempdir().unwrap();
+    RememberDeviceStore::new(dir.path())
+        .save("user@example.com", Some("account-1"), "rd-one")
+        .unwrap();
+
+    let data = std::fs::read(dir.path().join("remember_device.dat")).unwrap();
+    let sealed = data.strip_prefix(b"enc:").unwrap();
+    let plaintext = FileKeyStore::open(dir.path())
+        .unwrap()
+        .decrypt(sealed)
+        .unwrap();
+    let cache = UserLoginCache::decode(plaintext.as_slice()).unwrap();
+    assert_eq!(cache.version, Some(Version::Version(1)));
+    assert_eq!(cache.users.len(), 1);
+
+    let user = &cache.users[0];
+    assert_eq!(
+        user.account_id,
+        Some(AccountId::AccountId("account-1".to_string()))
+    );
+    assert_eq!(
+        user.email_hash,
+        Some(EmailHash::EmailHash(email_hash("user@example.com")))
+    );
+    assert_eq!(
+        user.rd_ticket,
+        Some(RdTicket::RdTicket("rd-one".to_string()))
+    );
+    assert!(!String::from_utf8_lossy(&data).contains("example.com"));
+    assert!(!String::from_utf8_lossy(&data).contains("rd-one"));
+}
+
+#[test]
+fn plaintext_files_are_read_and_sealed() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("remember_device.dat");
+    let cache = UserLoginCache {
+        version: Some(Version::Version(1)),
+        users: vec![User {
+            account_id: None,
+            email_hash: Some(EmailHash::EmailHash(email_hash("user@example.com"))),
+            rd_ticket: Some(RdTicket::RdTicket("rd-plain".to_string())),
+        }],
+    };
+    std::fs::write(&path, cache.encode_to_vec()).unwrap();
+
+    let store = RememberDeviceStore::new(dir.path());
+    assert_eq!(
+        store.ticket("user@example.com").unwrap().as_deref(),
+        Some("rd-plain")
+    );
+
+    let data = std::fs::read(&path).unwrap();
+    assert!(data.starts_with(b"enc:"));
+    assert!(!String::from_utf8_lossy(&data).contains("rd-plain"));
+    assert_eq!(
+        store.ticket("user@example.com").unwrap().as_deref(),
+        Some("rd-plain")
+    );
+}
+
+#[test]
+fn config_loads_the_ticket_for_its_email() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(
+        dir.path().join("dbdata.ini"),
+        "[Uplay]\nemail=user@example.com\npassword=hunter2\n",
+    )
+    .unwrap();
+    RememberDeviceStore::new(dir.path())
+        .save("user@example.com", None, "rd-one")
+        .unwrap();
+
+    let config = DbDataConfig::load(dir.path()).unwrap();
+    assert_eq!(config.remember_device_ticket.as_deref(), Some("rd-one"));
+}
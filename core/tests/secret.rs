@@ -0,0 +1,106 @@
+use dbdata_core::config::DbDataConfig;
+use dbdata_core::error::DbDataError;
+use dbdata_core::secret::{FileKeyStore, SecretStore, is_sealed, seal, unseal};
+use dbdata_core::token::{Settings, Token};
+
+const APP_ID: u32 = 4553;
+
+#[test]
+fn passphrase_store_round_trips_and_rejects_other_keys() {
+    let store = FileKeyStore::from_passphrase("correct horse");
+    let sealed = seal(&store, "hunter2").unwrap();
+    assert!(is_sealed(&sealed));
+    assert!(!sealed.contains("hunter2"));
+    assert_eq!(unseal(&store, &sealed).unwrap(), "hunter2");
+
+    // A fresh nonce every time, so equal values do not look equal on disk.
+    assert_ne!(seal(&store, "hunter2").unwrap(), sealed);
+
+    let other = FileKeyStore::from_passphrase("battery staple");
+    let err = unseal(&other, &sealed).unwrap_err();
+    assert!(matches!(err, DbDataError::Secret(_)), "{:?}", err);
+
+    assert_eq!(unseal(&store, "plaintext").unwrap(), "plaintext");
+    assert_eq!(seal(&store, "").unwrap(), "");
+}
+
+#[test]
+fn key_file_is_created_once_and_reused() {
+    let dir = tempfile::tempdir().unwrap();
+    let sealed = FileKeyStore::open(dir.path())
+        .unwrap()
+        .encrypt(b"ticket")
+        .unwrap();
+    assert_eq!(
+        std::fs::read(dir.path().join("dbdata.key")).unwrap().len(),
+        32
+    );
+
+    let reopened = FileKeyStore::open(dir.path()).unwrap();
+    assert_eq!(reopened.decrypt(&sealed).unwrap(), b"ticket");
+}
+
+#[test]
+fn plaintext_ini_values_are_migrated_on_load() {
+    let dir = tempfile::tempdir().unwrap();
+    let ini_path = dir.path().join("dbdata.ini");
+    std::fs::write(
+        &ini_path,
+        "[Uplay]\nemail=user@example.com\npassword=hunter2\n\
+         [token]\ntoken=game-token\nownership=ownership-token\n\
+         [settings]\ndlcs=101\n",
+    )
+    .unwrap();
+
+    let config = DbDataConfig::load(dir.path()).unwrap();
+    assert_eq!(config.email, "user@example.com");
+    assert_eq!(config.password, "hunter2");
+
+    let content = std::fs::read_to_string(&ini_path).unwrap();
+    for secret in [
+        "user@example.com",
+        "hunter2",
+        "game-token",
+        "ownership-token",
+    ] {
+        assert!(!content.contains(secret), "{}", content);
+    }
+    assert!(content.contains("dlcs=101"), "{}", content);
+
+    let settings = Settings::new(dir.path(), APP_ID).unwrap();
+    assert_eq!(settings.token.token, "game-token");
+    assert_eq!(settings.token.ownership.as_deref(), Some("ownership-token"));
+}
+
+#[test]
+fn saved_tickets_and_tokens_are_encrypted() {
+    let dir = tempfile::tempdir().unwrap();
+    DbDataConfig::create_default(dir.path()).unwrap();
+
+    DbDataConfig::save_remember_me_ticket(dir.path(), "remember-me").unwrap();
+    Token::from_values("game-token".into(), None)
+        .save_with_dlcs(dir.path(), APP_ID, &[])
+        .unwrap();
+
+    let content = std::fs::read_to_string(dir.path().join("dbdata.ini")).unwrap();
+    assert!(!content.contains("remember-me"), "{}", content);
+    assert!(!content.contains("game-token"), "{}", content);
+
+    let config = DbDataConfig::load(dir.path()).unwrap();
+    assert_eq!(config.remember_me_ticket.as_deref(), Some("remember-me"));
+}
+
+#[test]
+fn values_sealed_with_a_lost_key_fail_to_load() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(
+        dir.path().join("dbdata.ini"),
+        "[Uplay]\nemail=user@example.com\npassword=hunter2\n",
+    )
+    .unwrap();
+    DbDataConfig::load(dir.path()).unwrap();
+
+    std::fs::remove_file(dir.path().join("dbdata.key")).unwrap();
+    let err = DbDataConfig::load(dir.path()).unwrap_err().to_string();
+    assert!(err.contains("email"), "{}", err);
+}
@@ -0,0 +1,138 @@
+use std::thread;
+
+use dbdata_core::auth::transport::{MemoryStream, duplex};
+use dbdata_core::auth::{DemuxSocket, read_frame, write_frame};
+use dbdata_core::error::DbDataError;
+use dbdata_core::proto::demux::{self, DataMessage, OpenConnectionRsp, Push};
+use dbdata_core::proto::denuvo::{self, GetGameTokenRsp, rsp::Result as DenuvoResult};
+use dbdata_core::services::DenuvoConnection;
+use prost::Message;
+
+const CONNECTION_ID: u32 = 5;
+
+fn recv(stream: &mut MemoryStream) -> demux::Upstream {
+    demux::Upstream::decode(read_frame(stream).unwrap().as_slice()).unwrap()
+}
+
+fn send(stream: &mut MemoryStream, downstream: demux::Downstream) {
+    write_frame(stream, &downstream.encode_to_vec()).unwrap();
+}
+
+/// Answers the `denuvo_service` open request.
+fn accept_connection(stream: &mut MemoryStream) {
+    let req = recv(stream).request.unwrap();
+    assert_eq!(
+        req.open_connection_req.unwrap().service_name,
+        "denuvo_service"
+    );
+    send(
+        stream,
+        demux::Downstream {
+            response: Some(demux::Rsp {
+                request_id: req.request_id,
+                open_connection_rsp: Some(OpenConnectionRsp {
+                    success: true,
+                    connection_id: CONNECTION_ID,
+                }),
+                ..Default::default()
+            }),
+            push: None,
+        },
+    );
+}
+
+fn recv_denuvo(stream: &mut MemoryStream) -> denuvo::Req {
+    let data = recv(stream).push.unwrap().data.unwrap();
+    assert_eq!(data.connection_id, CONNECTION_ID);
+    denuvo::Upstream::decode(&data.data[4..])
+        .unwrap()
+        .request
+        .unwrap()
+}
+
+fn send_denuvo(stream: &mut MemoryStream, rsp: denuvo::Rsp) {
+    let payload = denuvo::Downstream {
+        response: Some(rsp),
+    }
+    .encode_to_vec();
+    let mut data = (payload.len() as u32).to_be_bytes().to_vec();
+    data.extend_from_slice(&payload);
+    send(
+        stream,
+        demux::Downstream {
+            response: None,
+            push: Some(Push {
+                data: Some(DataMessage {
+                    connection_id: CONNECTION_ID,
+                    data,
+                }),
+                ..Default::default()
+            }),
+        },
+    );
+}
+
+fn game_token_rsp(request_id: u32, token: &[u8]) -> denuvo::Rsp {
+    denuvo::Rsp {
+        request_id,
+        get_game_token_rsp: Some(GetGameTokenRsp {
+            game_token: token.to_vec(),
+        }),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn skips_responses_to_other_requests() {
+    let (client, mut server) = duplex();
+    let socket = DemuxSocket::from_transport(client).unwrap();
+
+    let server = thread::spawn(move || {
+        accept_connection(&mut server);
+
+        let req = recv_denuvo(&mut server);
+        assert_eq!(req.request_id, 1);
+        send_denuvo(&mut server, game_token_rsp(42, b"stale-token"));
+        send_denuvo(&mut server, game_token_rsp(req.request_id, b"game-token"));
+
+        recv_denuvo(&mut server).request_id
+    });
+
+    let mut denuvo = DenuvoConnection::new(&socket).unwrap();
+    assert_eq!(
+        denuvo.get_game_token("ownership", "request").unwrap(),
+        "game-token"
+    );
+    let err = denuvo.get_game_token("ownership", "request").unwrap_err();
+    assert!(
+        matches!(err, DbDataError::ConnectionClosed(None)),
+        "{:?}",
+        err
+    );
+    assert_eq!(server.join().unwrap(), 2);
+}
+
+#[test]
+fn maps_failed_result_codes() {
+    let (client, mut server) = duplex();
+    let socket = DemuxSocket::from_transport(client).unwrap();
+
+    let server = thread::spawn(move || {
+        accept_connection(&mut server);
+
+        let req = recv_denuvo(&mut server);
+        send_denuvo(
+            &mut server,
+            denuvo::Rsp {
+                request_id: req.request_id,
+                result: DenuvoResult::TimeOut as i32,
+                ..Default::default()
+            },
+        );
+    });
+
+    let mut denuvo = DenuvoConnection::new(&socket).unwrap();
+    let err = denuvo.get_game_token("ownership", "request").unwrap_err();
+    assert!(matches!(err, DbDataError::DenuvoTimeOut), "{:?}", err);
+    server.join().unwrap();
+}
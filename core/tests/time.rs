@@ -0,0 +1,53 @@
+use std::time::{Duration, UNIX_EPOCH};
+
+use dbdata_core::time::{apply_skew, clock_skew, parse_http_date, parse_rfc3339};
+
+#[test]
+fn parses_ubiservices_timestamps() {
+    assert_eq!(parse_rfc3339("1970-01-01T00:00:00Z"), Some(UNIX_EPOCH));
+    assert_eq!(
+        parse_rfc3339("2024-03-01T12:34:56.5000000Z"),
+        Some(UNIX_EPOCH + Duration::from_millis(1_709_296_496_500))
+    );
+}
+
+#[test]
+fn rejects_malformed_timestamps() {
+    for value in [
+        "",
+        "2024-03-01",
+        "2024-03-01T12:34:56",
+        "2024-13-01T00:00:00Z",
+        "2024-03-01T24:00:00Z",
+        "2024-03-01T12:34:56.12345678é9Z",
+        "2024-03-01T12:34:56.-1Z",
+        "99999999999999-03-01T00:00:00Z",
+        "yesterday",
+    ] {
+        assert_eq!(parse_rfc3339(value), None, "{}", value);
+    }
+}
+
+#[test]
+fn parses_http_dates() {
+    assert_eq!(
+        parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"),
+        Some(UNIX_EPOCH + Duration::from_secs(784_111_777))
+    );
+    assert_eq!(parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"), None);
+    assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 UTC"), None);
+    assert_eq!(parse_http_date("Sun, 06 Foo 1994 08:49:37 GMT"), None);
+}
+
+#[test]
+fn skew_is_signed_and_reversible() {
+    let local = UNIX_EPOCH + Duration::from_secs(1_000);
+    let ahead = local + Duration::from_secs(90);
+    let behind = local - Duration::from_secs(90);
+
+    assert_eq!(clock_skew(ahead, local), 90);
+    assert_eq!(clock_skew(behind, local), -90);
+    assert_eq!(apply_skew(local, clock_skew(ahead, local)), Some(ahead));
+    assert_eq!(apply_skew(local, clock_skew(behind, local)), Some(behind));
+    assert_eq!(apply_skew(local, i64::MAX), None);
+}
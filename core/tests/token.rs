@@ -0,0 +1,287 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use dbdata_core::config::DbDataConfig;
+use dbdata_core::token::{Settings, Token, fingerprint};
+
+const APP_ID: u32 = 4553;
+
+#[test]
+fn default_config_has_no_credentials() {
+    let dir = tempfile::tempdir().unwrap();
+    assert!(!DbDataConfig::exists(dir.path()));
+
+    DbDataConfig::create_default(dir.path()).unwrap();
+    assert!(DbDataConfig::exists(dir.path()));
+
+    let config = DbDataConfig::load(dir.path()).unwrap();
+    assert!(!config.has_credentials());
+    assert!(Settings::new(dir.path(), APP_ID).is_err());
+}
+
+#[test]
+fn saved_token_round_trips_through_settings() {
+    let dir = tempfile::tempdir().unwrap();
+    DbDataConfig::create_default(dir.path()).unwrap();
+
+    let token = Token::from_values("game-token".into(), Some("ownership-token".into()));
+    token
+        .save_with_dlcs(dir.path(), APP_ID, &[101, 202])
+        .unwrap();
+
+    let settings = Settings::new(dir.path(), APP_ID).unwrap();
+    assert_eq!(settings.token.token, "game-token");
+    assert_eq!(settings.token.ownership.as_deref(), Some("ownership-token"));
+    assert_eq!(settings.dlcs, vec![101, 202]);
+}
+
+#[test]
+fn missing_dlcs_are_empty_even_with_an_emulator_list() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(
+        dir.path().join("dbdata.ini"),
+        "[Uplay]\n[token]\ntoken=pasted-token\n",
+    )
+    .unwrap();
+    std::fs::write(dir.path().join("upc_r2.ini"), "[DLC]\n101\n202\n").unwrap();
+
+    let settings = Settings::new(dir.path(), APP_ID).unwrap();
+    assert_eq!(settings.token.token, "pasted-token");
+    assert!(settings.dlcs.is_empty());
+}
+
+#[test]
+fn saving_keeps_credentials() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(
+        dir.path().join("dbdata.ini"),
+        "[Uplay]\nemail=user@example.com\npassword=hunter2\n",
+    )
+    .unwrap();
+
+    Token::from_values("game-token".into(), None)
+        .save_with_dlcs(dir.path(), APP_ID, &[])
+        .unwrap();
+
+    let config = DbDataConfig::load(dir.path()).unwrap();
+    assert_eq!(config.email, "user@example.com");
+    assert!(config.has_credentials());
+
+    let settings = Settings::new(dir.path(), APP_ID).unwrap();
+    assert_eq!(settings.token.ownership, None);
+    assert!(settings.dlcs.is_empty());
+}
+
+#[test]
+fn saving_a_remember_me_ticket_clears_the_password() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(
+        dir.path().join("dbdata.ini"),
+        "[Uplay]\nemail=user@example.com\npassword=hunter2\n[token]\ntoken=game-token\n",
+    )
+    .unwrap();
+
+    DbDataConfig::save_remember_me_ticket(dir.path(), "remember-me").unwrap();
+
+    let config = DbDataConfig::load(dir.path()).unwrap();
+    assert_eq!(config.email, "user@example.com");
+    assert!(config.password.is_empty());
+    assert_eq!(config.remember_me_ticket.as_deref(), Some("remember-me"));
+    assert!(!config.has_password());
+    assert!(config.has_credentials());
+
+    let settings = Settings::new(dir.path(), APP_ID).unwrap();
+    assert_eq!(settings.token.token, "game-token");
+}
+
+#[test]
+fn lifetime_round_trips_and_uses_the_server_clock() {
+    let dir = tempfile::tempdir().unwrap();
+    DbDataConfig::create_default(dir.path()).unwrap();
+
+    // The server's clock is an hour behind ours, so a token that expires
+    // in 30 minutes by our clock still has 90 minutes left.
+    let server_now = SystemTime::now() - Duration::from_secs(3600);
+    let expires_at = SystemTime::now() + Duration::from_secs(30 * 60);
+    let token = Token::from_values("game-token".into(), None).with_lifetime(
+        server_now,
+        Some(expires_at),
+        -3600,
+    );
+    assert!(!token.expires_within(Duration::from_secs(60 * 60)));
+    assert!(token.expires_within(Duration::from_secs(2 * 60 * 60)));
+    token.save_with_dlcs(dir.path(), APP_ID, &[]).unwrap();
+
+    let loaded = Settings::new(dir.path(), APP_ID).unwrap().token;
+    let secs = |time: SystemTime| time.duration_since(UNIX_EPOCH).unwrap().as_secs();
+    assert_eq!(loaded.issued_at.map(secs), Some(secs(server_now)));
+    assert_eq!(loaded.expires_at.map(secs), Some(secs(expires_at)));
+    assert_eq!(loaded.clock_skew, -3600);
+}
+
+#[test]
+fn out_of_range_lifetimes_in_the_ini_do_not_panic() {
+    let dir = tempfile::tempdir().unwrap();
+    DbDataConfig::create_default(dir.path()).unwrap();
+    Token::from_values("game-token".into(), None)
+        .save_with_dlcs(dir.path(), APP_ID, &[])
+        .unwrap();
+
+    let ini_path = dir.path().join("dbdata.ini");
+    let content = std::fs::read_to_string(&ini_path).unwrap();
+    std::fs::write(
+        &ini_path,
+        content.replace(
+            &format!("[token.{}]\n", APP_ID),
+            &format!(
+                "[token.{}]\nissued_at={}\nexpires_at={}\nclock_skew={}\n",
+                APP_ID,
+                i64::MAX,
+                i64::MAX,
+                i64::MAX
+            ),
+        ),
+    )
+    .unwrap();
+
+    // Loading must not panic, and a skew that cannot be applied makes the
+    // token due rather than valid forever.
+    let token = Settings::new(dir.path(), APP_ID).unwrap().token;
+    assert!(token.expires_within(Duration::from_secs(60)));
+    token.refresh_at(Duration::from_secs(60));
+}
+
+#[test]
+fn tokens_without_an_expiry_never_expire() {
+    let token = Token::from_values("game-token".into(), None);
+    assert!(!token.expires_within(Duration::from_secs(365 * 24 * 3600)));
+}
+
+#[test]
+fn short_lived_tokens_are_refreshed_halfway() {
+    let margin = Duration::from_secs(10 * 60);
+    let issued_at = SystemTime::now();
+
+    // A time trial token that lasts five minutes is due after two and a half.
+    let trial = Token::from_values("time-token".into(), None).with_lifetime(
+        issued_at,
+        Some(issued_at + Duration::from_secs(5 * 60)),
+        0,
+    );
+    assert_eq!(trial.refresh_margin(margin), Duration::from_secs(150));
+    assert!(!trial.expires_within(trial.refresh_margin(margin)));
+    assert_eq!(
+        trial.refresh_at(margin),
+        Some(issued_at + Duration::from_secs(150))
+    );
+
+    let day = Token::from_values("game-token".into(), None).with_lifetime(
+        issued_at,
+        Some(issued_at + Duration::from_secs(24 * 3600)),
+        60,
+    );
+    assert_eq!(day.refresh_margin(margin), margin);
+    // Expiry is by the server's clock, which is a minute ahead of ours.
+    assert_eq!(
+        day.refresh_at(margin),
+        Some(issued_at + Duration::from_secs(24 * 3600 - 60) - margin)
+    );
+
+    assert_eq!(
+        Token::from_values("x".into(), None).refresh_at(margin),
+        None
+    );
+}
+
+#[test]
+fn tokens_are_kept_per_app() {
+    let dir = tempfile::tempdir().unwrap();
+    DbDataConfig::create_default(dir.path()).unwrap();
+
+    Token::from_values("game-token".into(), Some("ownership-token".into()))
+        .save_with_dlcs(dir.path(), APP_ID, &[101])
+        .unwrap();
+    Token::from_values("other-token".into(), None)
+        .save_with_dlcs(dir.path(), 4554, &[202, 303])
+        .unwrap();
+
+    let settings = Settings::new(dir.path(), APP_ID).unwrap();
+    assert_eq!(settings.token.token, "game-token");
+    assert_eq!(settings.token.ownership.as_deref(), Some("ownership-token"));
+    assert_eq!(settings.dlcs, vec![101]);
+
+    let other = Settings::new(dir.path(), 4554).unwrap();
+    assert_eq!(other.token.token, "other-token");
+    assert_eq!(other.token.ownership, None);
+    assert_eq!(other.dlcs, vec![202, 303]);
+}
+
+#[test]
+fn apps_without_an_entry_use_the_shared_section() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(
+        dir.path().join("dbdata.ini"),
+        "[Uplay]\n[token]\ntoken=pasted-token\nownership=\n[settings]\ndlcs=7\n",
+    )
+    .unwrap();
+    Token::from_values("game-token".into(), None)
+        .save_with_dlcs(dir.path(), APP_ID, &[])
+        .unwrap();
+
+    let pasted = Settings::new(dir.path(), 4554).unwrap();
+    assert_eq!(pasted.token.token, "pasted-token");
+    assert_eq!(pasted.dlcs, vec![7]);
+
+    let own = Settings::new(dir.path(), APP_ID).unwrap();
+    assert_eq!(own.token.token, "game-token");
+    assert!(own.dlcs.is_empty());
+}
+
+#[test]
+fn invalidating_removes_only_that_apps_token() {
+    let dir = tempfile::tempdir().unwrap();
+    DbDataConfig::create_default(dir.path()).unwrap();
+    Token::from_values("game-token".into(), None)
+        .save_with_dlcs(dir.path(), APP_ID, &[101])
+        .unwrap();
+    Token::from_values("other-token".into(), None)
+        .save_with_dlcs(dir.path(), 4554, &[])
+        .unwrap();
+
+    Token::invalidate(dir.path(), APP_ID).unwrap();
+    assert!(Settings::new(dir.path(), APP_ID).is_err());
+    assert_eq!(
+        Settings::new(dir.path(), 4554).unwrap().token.token,
+        "other-token"
+    );
+
+    // A token read from the shared section is emptied there.
+    std::fs::write(
+        dir.path().join("dbdata.ini"),
+        "[Uplay]\n[token]\ntoken=pasted-token\nownership=\n",
+    )
+    .unwrap();
+    Token::invalidate(dir.path(), APP_ID).unwrap();
+    assert!(Settings::new(dir.path(), APP_ID).is_err());
+}
+
+#[test]
+fn fingerprint_tells_which_build_a_token_was_issued_for() {
+    let dir = tempfile::tempdir().unwrap();
+    DbDataConfig::create_default(dir.path()).unwrap();
+
+    let issued_for = fingerprint(APP_ID, "Game.exe", "request-token");
+    assert_eq!(issued_for, fingerprint(APP_ID, "game.exe", "request-token"));
+    Token::from_values("game-token".into(), None)
+        .with_fingerprint(issued_for.clone())
+        .save_with_dlcs(dir.path(), APP_ID, &[])
+        .unwrap();
+
+    let token = Settings::new(dir.path(), APP_ID).unwrap().token;
+    assert!(token.matches_fingerprint(&issued_for));
+    assert!(!token.matches_fingerprint(&fingerprint(APP_ID, "game.exe", "patched-request")));
+    assert!(!token.matches_fingerprint(&fingerprint(4554, "game.exe", "request-token")));
+    assert!(!token.matches_fingerprint(&fingerprint(APP_ID, "launcher.exe", "request-token")));
+
+    let pasted = Token::from_values("pasted-token".into(), None);
+    assert!(pasted.matches_fingerprint(&issued_for));
+}
@@ -0,0 +1,147 @@
+use dbdata_core::config::DbDataConfig;
+use dbdata_core::error::DbDataError;
+use dbdata_core::proto::user_dat_file::{
+    Cache, EnvironmentCache, LegacyVulnerableUnversionedCache, StartupEntry, UserInfo, cache,
+    environment_cache, startup_entry,
+    user_info::{Email, RememberMeTicket, UbiProfileId, Username},
+};
+use dbdata_core::user_cache::UserCache;
+use prost::Message;
+
+fn user(email: &str, ticket: Option<&str>) -> UserInfo {
+    UserInfo {
+        username: Some(Username::Username(email.split('@').next().unwrap().into())),
+        email: Some(Email::Email(email.into())),
+        ubi_profile_id: Some(UbiProfileId::UbiProfileId(format!("profile-{}", email))),
+        remember_me_ticket: ticket.map(|t| RememberMeTicket::RememberMeTicket(t.into())),
+        ..Default::default()
+    }
+}
+
+fn cache(users: Vec<UserInfo>, startup_index: Option<u32>) -> Vec<u8> {
+    Cache {
+        prod: Some(cache::Prod::Prod(EnvironmentCache {
+            users,
+            startup_entry: startup_index.map(|index| {
+                environment_cache::StartupEntry::StartupEntry(StartupEntry {
+                    user_index: Some(startup_entry::UserIndex::UserIndex(index)),
+                    ..Default::default()
+                })
+            }),
+        })),
+        ..Default::default()
+    }
+    .encode_to_vec()
+}
+
+#[test]
+fn picks_the_named_account_or_the_only_one_with_a_ticket() {
+    let cache = UserCache::decode(&cache(
+        vec![
+            user("first@example.com", Some("rm-first")),
+            user("second@example.com", Some("rm-second")),
+            user("third@example.com", None),
+        ],
+        None,
+    ))
+    .unwrap();
+    assert_eq!(cache.accounts().len(), 3);
+
+    let second = cache.select(Some("SECOND")).unwrap();
+    assert_eq!(second.remember_me_ticket.as_deref(), Some("rm-second"));
+    let first = cache.select(Some("profile-first@example.com")).unwrap();
+    assert_eq!(first.email.as_deref(), Some("first@example.com"));
+
+    let err = cache.select(None).unwrap_err().to_string();
+    assert!(
+        err.contains("first@example.com, second@example.com"),
+        "{}",
+        err
+    );
+    assert!(cache.select(Some("third")).is_err());
+
+    let single = UserCache::decode(&cache_with_one()).unwrap();
+    assert_eq!(
+        single.select(None).unwrap().remember_me_ticket.as_deref(),
+        Some("rm-only")
+    );
+}
+
+fn cache_with_one() -> Vec<u8> {
+    cache(
+        vec![
+            user("none@example.com", None),
+            user("only@example.com", Some("rm-only")),
+        ],
+        None,
+    )
+}
+
+#[test]
+fn falls_back_to_the_startup_account() {
+    let cache = UserCache::decode(&cache(
+        vec![
+            user("first@example.com", Some("rm-first")),
+            user("second@example.com", Some("rm-second")),
+        ],
+        Some(1),
+    ))
+    .unwrap();
+    assert_eq!(
+        cache.select(None).unwrap().email.as_deref(),
+        Some("second@example.com")
+    );
+}
+
+#[test]
+fn reads_the_unversioned_format() {
+    let data = LegacyVulnerableUnversionedCache {
+        users: vec![user("legacy@example.com", Some("rm-legacy"))],
+    }
+    .encode_to_vec();
+
+    let cache = UserCache::decode(&data).unwrap();
+    assert_eq!(
+        cache.select(None).unwrap().remember_me_ticket.as_deref(),
+        Some("rm-legacy")
+    );
+}
+
+#[test]
+fn rejects_files_that_are_not_a_cache() {
+    let err = UserCache::decode(b"\xff\xff\xff\xff").unwrap_err();
+    assert!(matches!(err, DbDataError::UserCache(_)), "{:?}", err);
+}
+
+#[test]
+fn config_imports_the_login_only_when_opted_in() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("user.dat"), cache_with_one()).unwrap();
+
+    std::fs::write(
+        dir.path().join("dbdata.ini"),
+        "[Uplay]\nemail=\npassword=\n",
+    )
+    .unwrap();
+    assert!(!DbDataConfig::load(dir.path()).unwrap().has_credentials());
+
+    std::fs::write(
+        dir.path().join("dbdata.ini"),
+        "[Uplay]\nemail=\npassword=\n[user_cache]\npath=user.dat\n",
+    )
+    .unwrap();
+    let config = DbDataConfig::load(dir.path()).unwrap();
+    assert_eq!(config.email, "only@example.com");
+    assert_eq!(config.remember_me_ticket.as_deref(), Some("rm-only"));
+    assert!(config.has_credentials());
+
+    // Credentials in dbdata.ini win over the cache.
+    std::fs::write(
+        dir.path().join("dbdata.ini"),
+        "[Uplay]\nemail=user@example.com\npassword=hunter2\n[user_cache]\npath=user.dat\n",
+    )
+    .unwrap();
+    let config = DbDataConfig::load(dir.path()).unwrap();
+    assert_eq!(config.email, "user@example.com");
+    assert_eq!(config.remember_me_ticket, None);
+}
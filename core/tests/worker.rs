@@ -0,0 +1,43 @@
+use std::sync::mpsc;
+use std::time::Duration;
+
+use dbdata_core::auth::{TokenWorker, WorkerStatus};
+use dbdata_core::error::DbDataError;
+
+#[test]
+fn reports_running_until_the_job_finishes() {
+    let (release, released) = mpsc::channel::<()>();
+    let worker = TokenWorker::spawn("test-worker", move || {
+        released.recv().unwrap();
+        Ok("game-token".to_string())
+    })
+    .unwrap();
+
+    assert_eq!(worker.status(), WorkerStatus::Running);
+    assert_eq!(
+        worker.wait(Some(Duration::from_millis(20))),
+        WorkerStatus::Running
+    );
+    assert!(worker.take().is_none());
+
+    release.send(()).unwrap();
+    assert_eq!(worker.wait(None), WorkerStatus::Done);
+    assert_eq!(worker.take().unwrap().unwrap(), "game-token");
+
+    // The result is handed out once, but the status stays.
+    assert!(worker.take().is_none());
+    assert_eq!(worker.status(), WorkerStatus::Done);
+}
+
+#[test]
+fn reports_failed_jobs() {
+    let worker = TokenWorker::<()>::spawn("test-worker", || {
+        Err(DbDataError::Protocol("boom".to_string()))
+    })
+    .unwrap();
+
+    assert_eq!(worker.wait(None), WorkerStatus::Failed);
+    let err = worker.take().unwrap().unwrap_err();
+    assert!(matches!(err, DbDataError::Protocol(_)), "{:?}", err);
+    assert_eq!(worker.status(), WorkerStatus::Failed);
+}
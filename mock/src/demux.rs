@@ -0,0 +1,488 @@
+#![allow(deprecated)]
+
+use prost::Message;
+use std::{
+    collections::HashMap,
+    error::Error,
+    io::{Read, Write},
+    net::{SocketAddr, TcpListener},
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use crate::fixture::{Action, Fixture, Trigger};
+use dbdata_core::auth::{read_frame, write_frame};
+use dbdata_core::proto::demux::{
+    AuthenticateRsp, ClientOutdatedPush, ConnectionClosedPush, DataMessage, Downstream,
+    GetPatchInfoRsp, KeepAlivePush, OpenConnectionRsp, Push, Req, Rsp, Upstream,
+    connection_closed_push::ConnectionErrorCode,
+};
+use dbdata_core::proto::{denuvo, ownership};
+
+/// What the mock has seen from its clients.
+#[derive(Debug, Clone, Default)]
+pub struct Stats {
+    pub connections: u32,
+    pub client_versions: Vec<u32>,
+    pub keep_alives: u32,
+    pub authenticated_tickets: Vec<String>,
+    pub opened_services: Vec<String>,
+}
+
+/// Shared state of a mock demux server: the fixture, the faults still to be
+/// injected and the traffic seen so far.
+pub struct MockDemux {
+    fixture: Fixture,
+    faults: Mutex<Vec<(Trigger, Action, u32)>>,
+    stats: Mutex<Stats>,
+}
+
+impl MockDemux {
+    pub fn new(fixture: Fixture) -> Self {
+        let faults = fixture
+            .faults
+            .iter()
+            .map(|f| (f.on, f.action.clone(), f.times))
+            .collect();
+
+        Self {
+            fixture,
+            faults: Mutex::new(faults),
+            stats: Mutex::new(Stats::default()),
+        }
+    }
+
+    pub fn stats(&self) -> Stats {
+        self.stats.lock().unwrap().clone()
+    }
+
+    fn take_fault(&self, trigger: Trigger) -> Option<Action> {
+        let mut faults = self.faults.lock().unwrap();
+        let (_, action, times) = faults
+            .iter_mut()
+            .find(|(on, _, times)| *on == trigger && *times > 0)?;
+        *times -= 1;
+        Some(action.clone())
+    }
+
+    /// Serves one client until it disconnects.
+    pub fn serve<T: Read + Write>(&self, mut stream: T) -> Result<(), Box<dyn Error>> {
+        self.stats.lock().unwrap().connections += 1;
+
+        let mut session = Session {
+            mock: self,
+            connections: HashMap::new(),
+            next_connection_id: 1,
+            disconnect: false,
+        };
+
+        while !session.disconnect {
+            let Ok(frame) = read_frame(&mut stream) else {
+                return Ok(());
+            };
+            let upstream = Upstream::decode(frame.as_slice())?;
+
+            if let Some(req) = upstream.request {
+                session.handle_request(&mut stream, req)?;
+            }
+            if let Some(push) = upstream.push {
+                session.handle_push(&mut stream, push)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A mock demux server listening on a local TCP port.
+pub struct MockDemuxServer {
+    addr: SocketAddr,
+    mock: Arc<MockDemux>,
+}
+
+impl MockDemuxServer {
+    /// Binds to `addr` and serves every incoming connection on its own thread.
+    pub fn start(fixture: Fixture, addr: &str) -> Result<Self, Box<dyn Error>> {
+        let listener = TcpListener::bind(addr)?;
+        let addr = listener.local_addr()?;
+        let mock = Arc::new(MockDemux::new(fixture));
+
+        let server = mock.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let _ = stream.set_nodelay(true);
+                let server = server.clone();
+                thread::spawn(move || {
+                    if let Err(e) = server.serve(stream) {
+                        log::warn!("Mock demux connection failed: {}", e);
+                    }
+                });
+            }
+        });
+
+        log::info!("Mock demux server listening on {}", addr);
+        Ok(Self { addr, mock })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn stats(&self) -> Stats {
+        self.mock.stats()
+    }
+}
+
+struct Session<'a> {
+    mock: &'a MockDemux,
+    connections: HashMap<u32, String>,
+    next_connection_id: u32,
+    /// Set by a `disconnect` fault; the connection is dropped once the
+    /// current frame is handled.
+    disconnect: bool,
+}
+
+impl Session<'_> {
+    fn handle_request<W: Write>(&mut self, stream: &mut W, req: Req) -> Result<(), Box<dyn Error>> {
+        let request_id = req.request_id;
+        let mut rsp = Rsp {
+            request_id,
+            ..Default::default()
+        };
+
+        let trigger = if let Some(patch) = req.get_patch_info_req {
+            rsp.get_patch_info_rsp = Some(GetPatchInfoRsp {
+                success: true,
+                patch_track_id: patch.patch_track_id,
+                test_config: patch.test_config,
+                patch_base_url: String::new(),
+                latest_version: self.mock.fixture.latest_version,
+                track_type: patch.track_type,
+            });
+            Trigger::PatchInfo
+        } else if let Some(auth) = req.authenticate_req {
+            let ticket = auth.token.ubi_ticket.unwrap_or_default();
+            let success = match self.mock.fixture.ticket {
+                Some(ref expected) => *expected == ticket,
+                None => !ticket.is_empty(),
+            };
+            self.mock
+                .stats
+                .lock()
+                .unwrap()
+                .authenticated_tickets
+                .push(ticket);
+            rsp.authenticate_rsp = Some(AuthenticateRsp {
+                success,
+                ..Default::default()
+            });
+            Trigger::Authenticate
+        } else if let Some(open) = req.open_connection_req {
+            let connection_id = self.next_connection_id;
+            self.next_connection_id += 1;
+            let success = matches!(
+                open.service_name.as_str(),
+                "ownership_service" | "denuvo_service"
+            );
+            if success {
+                self.connections
+                    .insert(connection_id, open.service_name.clone());
+            }
+            self.mock
+                .stats
+                .lock()
+                .unwrap()
+                .opened_services
+                .push(open.service_name);
+            rsp.open_connection_rsp = Some(OpenConnectionRsp {
+                connection_id,
+                success,
+            });
+            Trigger::OpenConnection
+        } else {
+            return Err(format!("Unsupported demux request {}", request_id).into());
+        };
+
+        let Some(action) = self.mock.take_fault(trigger) else {
+            return send_downstream(stream, Some(rsp), None);
+        };
+
+        match action {
+            Action::KeepAlive => {
+                send_keep_alive(stream)?;
+                send_downstream(stream, Some(rsp), None)
+            }
+            Action::ConnectionClosed { error_code } => send_closed(stream, 0, error_code),
+            Action::ClientOutdated => send_outdated(stream),
+            Action::Reject => {
+                if let Some(ref mut auth) = rsp.authenticate_rsp {
+                    auth.success = false;
+                }
+                if let Some(ref mut open) = rsp.open_connection_rsp {
+                    open.success = false;
+                }
+                if let Some(ref mut patch) = rsp.get_patch_info_rsp {
+                    patch.success = false;
+                }
+                send_downstream(stream, Some(rsp), None)
+            }
+            Action::DenuvoResult { .. } => send_downstream(stream, Some(rsp), None),
+            Action::Disconnect => {
+                self.disconnect = true;
+                Ok(())
+            }
+            Action::Delay { ms } => {
+                thread::sleep(Duration::from_millis(ms));
+                send_downstream(stream, Some(rsp), None)
+            }
+        }
+    }
+
+    fn handle_push<W: Write>(&mut self, stream: &mut W, push: Push) -> Result<(), Box<dyn Error>> {
+        if push.keep_alive.is_some() {
+            self.mock.stats.lock().unwrap().keep_alives += 1;
+        }
+        if let Some(version) = push.client_version {
+            self.mock
+                .stats
+                .lock()
+                .unwrap()
+                .client_versions
+                .push(version.version);
+        }
+        if let Some(data) = push.data {
+            self.handle_data(stream, data)?;
+        }
+        Ok(())
+    }
+
+    fn handle_data<W: Write>(
+        &mut self,
+        stream: &mut W,
+        data: DataMessage,
+    ) -> Result<(), Box<dyn Error>> {
+        let connection_id = data.connection_id;
+        let service = self
+            .connections
+            .get(&connection_id)
+            .ok_or_else(|| format!("Data for unknown connection {}", connection_id))?
+            .clone();
+
+        let payload = unprefix(&data.data)?;
+        let (trigger, reply) = match service.as_str() {
+            "ownership_service" => self.ownership(payload)?,
+            _ => self.denuvo(payload)?,
+        };
+
+        let reply = match self.mock.take_fault(trigger) {
+            None => reply,
+            Some(Action::KeepAlive) => {
+                send_keep_alive(stream)?;
+                reply
+            }
+            Some(Action::ConnectionClosed { error_code }) => {
+                return send_closed(stream, connection_id, error_code);
+            }
+            Some(Action::ClientOutdated) => return send_outdated(stream),
+            Some(Action::Disconnect) => {
+                self.disconnect = true;
+                return Ok(());
+            }
+            Some(Action::Delay { ms }) => {
+                thread::sleep(Duration::from_millis(ms));
+                reply
+            }
+            Some(Action::DenuvoResult { result }) => {
+                let mut downstream = denuvo::Downstream::decode(reply.as_slice())?;
+                if let Some(ref mut rsp) = downstream.response {
+                    rsp.result = denuvo::rsp::Result::from_str_name(&result)
+                        .map(|r| r as i32)
+                        .unwrap_or(denuvo::rsp::Result::Failure as i32);
+                    rsp.get_game_token_rsp = None;
+                    rsp.get_game_time_token_rsp = None;
+                    rsp.get_ownership_list_token_rsp = None;
+                }
+                downstream.encode_to_vec()
+            }
+            Some(Action::Reject) => {
+                let mut downstream = ownership::Downstream::decode(reply.as_slice())?;
+                if let Some(rsp) = downstream.response.as_mut() {
+                    if let Some(init) = rsp.initialize_rsp.as_mut() {
+                        init.success = false;
+                        init.owned_games = None;
+                    }
+                    if let Some(token) = rsp.ownership_token_rsp.as_mut() {
+                        token.success = Some(false);
+                        token.token = None;
+                    }
+                }
+                downstream.encode_to_vec()
+            }
+        };
+
+        let mut prefixed = (reply.len() as u32).to_be_bytes().to_vec();
+        prefixed.extend_from_slice(&reply);
+
+        send_downstream(
+            stream,
+            None,
+            Some(Push {
+                data: Some(DataMessage {
+                    connection_id,
+                    data: prefixed,
+                }),
+                ..Default::default()
+            }),
+        )
+    }
+
+    fn ownership(&self, payload: &[u8]) -> Result<(Trigger, Vec<u8>), Box<dyn Error>> {
+        let req = ownership::Upstream::decode(payload)?
+            .request
+            .ok_or("Ownership upstream without request")?;
+        let fixture = &self.mock.fixture;
+
+        let mut rsp = ownership::Rsp {
+            request_id: req.request_id,
+            ..Default::default()
+        };
+
+        let trigger = if req.initialize_req.is_some() {
+            let owned_games = fixture
+                .games
+                .iter()
+                .map(|g| ownership::OwnedGame {
+                    product_id: g.product_id,
+                    owned: Some(g.owned),
+                    product_associations: g.associations.clone(),
+                    denuvo_activation_overwrite: g.time_trial.then_some(
+                        ownership::owned_game::DenuvoActivationOverwrite::TimeTrial as i32,
+                    ),
+                    ..Default::default()
+                })
+                .collect();
+            rsp.initialize_rsp = Some(ownership::InitializeRsp {
+                success: true,
+                owned_games: Some(ownership::OwnedGames { owned_games }),
+                ..Default::default()
+            });
+            Trigger::Initialize
+        } else if req.ownership_token_req.is_some() {
+            rsp.ownership_token_rsp = Some(ownership::OwnershipTokenRsp {
+                success: Some(true),
+                token: Some(fixture.ownership_token.clone()),
+                expiration: Some(fixture.ownership_token_expiration),
+            });
+            Trigger::OwnershipToken
+        } else {
+            return Err(format!("Unsupported ownership request {}", req.request_id).into());
+        };
+
+        let downstream = ownership::Downstream {
+            response: Some(rsp),
+            ..Default::default()
+        };
+        Ok((trigger, downstream.encode_to_vec()))
+    }
+
+    fn denuvo(&self, payload: &[u8]) -> Result<(Trigger, Vec<u8>), Box<dyn Error>> {
+        let req = denuvo::Upstream::decode(payload)?
+            .request
+            .ok_or("Denuvo upstream without request")?;
+        let fixture = &self.mock.fixture;
+
+        let mut rsp = denuvo::Rsp {
+            request_id: req.request_id,
+            result: denuvo::rsp::Result::Success as i32,
+            ..Default::default()
+        };
+
+        let trigger = if req.get_game_token_req.is_some() {
+            rsp.get_game_token_rsp = Some(denuvo::GetGameTokenRsp {
+                game_token: fixture.game_token.clone().into_bytes(),
+            });
+            Trigger::GameToken
+        } else if req.get_game_time_token_req.is_some() {
+            rsp.get_game_time_token_rsp = Some(denuvo::GetGameTimeTokenRsp {
+                time_token: fixture.time_token.clone().into_bytes(),
+                time_token_ttl_sec: fixture.time_token_ttl_sec,
+            });
+            Trigger::GameTimeToken
+        } else if req.get_ownership_list_token_req.is_some() {
+            rsp.get_ownership_list_token_rsp = Some(denuvo::GetOwnershipListTokenRsp {
+                ownership_list_token: fixture.ownership_list_token.clone().into_bytes(),
+            });
+            Trigger::OwnershipListToken
+        } else {
+            return Err(format!("Unsupported denuvo request {}", req.request_id).into());
+        };
+
+        let downstream = denuvo::Downstream {
+            response: Some(rsp),
+        };
+        Ok((trigger, downstream.encode_to_vec()))
+    }
+}
+
+fn unprefix(data: &[u8]) -> Result<&[u8], Box<dyn Error>> {
+    if data.len() < 4 {
+        return Err("Service payload too short".into());
+    }
+    let len = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize;
+    data.get(4..4 + len)
+        .ok_or_else(|| "Service payload truncated".into())
+}
+
+fn send_downstream<W: Write>(
+    stream: &mut W,
+    response: Option<Rsp>,
+    push: Option<Push>,
+) -> Result<(), Box<dyn Error>> {
+    let downstream = Downstream { response, push };
+    Ok(write_frame(stream, &downstream.encode_to_vec())?)
+}
+
+fn send_keep_alive<W: Write>(stream: &mut W) -> Result<(), Box<dyn Error>> {
+    send_downstream(
+        stream,
+        None,
+        Some(Push {
+            keep_alive: Some(KeepAlivePush {}),
+            ..Default::default()
+        }),
+    )
+}
+
+fn send_closed<W: Write>(
+    stream: &mut W,
+    connection_id: u32,
+    error_code: Option<String>,
+) -> Result<(), Box<dyn Error>> {
+    let error_code = error_code
+        .as_deref()
+        .and_then(ConnectionErrorCode::from_str_name)
+        .map(|c| c as i32);
+
+    send_downstream(
+        stream,
+        None,
+        Some(Push {
+            connection_closed: Some(ConnectionClosedPush {
+                connection_id,
+                error_code,
+            }),
+            ..Default::default()
+        }),
+    )
+}
+
+fn send_outdated<W: Write>(stream: &mut W) -> Result<(), Box<dyn Error>> {
+    send_downstream(
+        stream,
+        None,
+        Some(Push {
+            client_outdated: Some(ClientOutdatedPush {}),
+            ..Default::default()
+        }),
+    )
+}
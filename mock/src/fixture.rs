@@ -0,0 +1,143 @@
+use serde::Deserialize;
+use std::{error::Error, path::Path};
+
+use dbdata_core::proto::demux::connection_closed_push::ConnectionErrorCode;
+use dbdata_core::proto::denuvo::rsp::Result as DenuvoResult;
+
+/// Scripted answers served by the mock demux server.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Fixture {
+    /// Version returned in `GetPatchInfoRsp`.
+    pub latest_version: u32,
+    /// When set, `AuthenticateReq` is only accepted with this ubi ticket.
+    pub ticket: Option<String>,
+    pub games: Vec<FixtureGame>,
+    pub ownership_token: String,
+    pub ownership_token_expiration: u64,
+    pub game_token: String,
+    /// Returned in `GetGameTimeTokenRsp` for games held as time trials.
+    pub time_token: String,
+    pub time_token_ttl_sec: u32,
+    pub ownership_list_token: String,
+    pub faults: Vec<Fault>,
+}
+
+impl Default for Fixture {
+    fn default() -> Self {
+        Self {
+            latest_version: 11200,
+            ticket: None,
+            games: vec![],
+            ownership_token: "mock-ownership-token".to_string(),
+            ownership_token_expiration: 0,
+            game_token: "mock-game-token".to_string(),
+            time_token: "mock-time-token".to_string(),
+            time_token_ttl_sec: 3600,
+            ownership_list_token: "mock-ownership-list-token".to_string(),
+            faults: vec![],
+        }
+    }
+}
+
+impl Fixture {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read fixture {:?}: {}", path, e))?;
+        Self::from_json(&content)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, Box<dyn Error>> {
+        let fixture: Self = serde_json::from_str(json)?;
+        for fault in &fixture.faults {
+            fault.action.validate()?;
+        }
+        Ok(fixture)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct FixtureGame {
+    pub product_id: u32,
+    pub owned: bool,
+    /// Product ids of DLCs associated with this game.
+    pub associations: Vec<u32>,
+    /// Reported with `DenuvoActivationOverwrite_TimeTrial`.
+    pub time_trial: bool,
+}
+
+impl Default for FixtureGame {
+    fn default() -> Self {
+        Self {
+            product_id: 0,
+            owned: true,
+            associations: vec![],
+            time_trial: false,
+        }
+    }
+}
+
+/// Point in the protocol where a fault is injected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Trigger {
+    PatchInfo,
+    Authenticate,
+    OpenConnection,
+    Initialize,
+    OwnershipToken,
+    GameToken,
+    GameTimeToken,
+    OwnershipListToken,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Fault {
+    pub on: Trigger,
+    #[serde(flatten)]
+    pub action: Action,
+    /// How many matching requests the fault applies to.
+    #[serde(default = "default_times")]
+    pub times: u32,
+}
+
+fn default_times() -> u32 {
+    1
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Action {
+    /// Sends a `KeepAlivePush` before the normal answer.
+    KeepAlive,
+    /// Sends a `ConnectionClosedPush` instead of the answer. `error_code` is
+    /// a `Connection_ErrorCode` name such as `Connection_MultipleLogin`.
+    ConnectionClosed { error_code: Option<String> },
+    /// Sends a `ClientOutdatedPush` instead of the answer.
+    ClientOutdated,
+    /// Answers a denuvo request with a `Rsp::Result` name such as `NotOwned`.
+    DenuvoResult { result: String },
+    /// Answers with `success: false`.
+    Reject,
+    /// Drops the connection without answering.
+    Disconnect,
+    /// Answers normally after waiting `ms` milliseconds.
+    Delay { ms: u64 },
+}
+
+impl Action {
+    fn validate(&self) -> Result<(), Box<dyn Error>> {
+        match self {
+            Action::ConnectionClosed {
+                error_code: Some(code),
+            } if ConnectionErrorCode::from_str_name(code).is_none() => {
+                Err(format!("Unknown Connection_ErrorCode: {}", code).into())
+            }
+            Action::DenuvoResult { result } if DenuvoResult::from_str_name(result).is_none() => {
+                Err(format!("Unknown denuvo result: {}", result).into())
+            }
+            _ => Ok(()),
+        }
+    }
+}
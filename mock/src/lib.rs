@@ -0,0 +1,10 @@
+//! Local stand-ins for the Ubisoft servers `dbdata-core` talks to, so the
+//! whole token flow can be exercised offline.
+
+pub mod demux;
+pub mod fixture;
+pub mod login;
+
+pub use demux::{MockDemux, MockDemuxServer, Stats};
+pub use fixture::Fixture;
+pub use login::{LoginReply, MockLoginServer};
@@ -0,0 +1,321 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    error::Error,
+    io::{BufRead, BufReader, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use dbdata_core::auth::LoginEndpoint;
+
+/// The `Date` header of successful replies, matching their `serverTime`.
+pub const SERVER_DATE: &str = "Mon, 01 Jan 2024 00:00:00 GMT";
+
+/// One scripted answer of the mock session endpoint.
+#[derive(Debug, Clone)]
+pub enum LoginReply {
+    Success {
+        ticket: String,
+        session_id: String,
+        name_on_platform: String,
+        remember_me_ticket: Option<String>,
+        remember_device_ticket: Option<String>,
+        /// Seconds between `serverTime` and `expiration`, under 30 days.
+        lifetime: u32,
+    },
+    /// 401 with ubiservices error code 1 ("Invalid credentials").
+    BadCredentials,
+    /// 200 carrying only a `twoFactorAuthenticationTicket`.
+    TwoFactorRequired { ticket: String },
+    /// 400 answering a two-factor code that does not match.
+    InvalidTwoFactorCode,
+    /// 429 with a `Retry-After` header.
+    RateLimited { retry_after: u32 },
+    /// 200 with a body that is not JSON.
+    MalformedJson,
+    /// Sends the wrapped reply with `Transfer-Encoding: chunked`.
+    Chunked(Box<LoginReply>),
+    /// 204 answering a session `DELETE`. Sent for every `DELETE` without
+    /// using up a scripted reply.
+    SessionDeleted,
+}
+
+impl LoginReply {
+    pub fn success() -> Self {
+        LoginReply::Success {
+            ticket: "mock-ticket".to_string(),
+            session_id: "mock-session".to_string(),
+            name_on_platform: "mock-user".to_string(),
+            remember_me_ticket: Some("mock-remember-me".to_string()),
+            remember_device_ticket: None,
+            lifetime: 3 * 3600,
+        }
+    }
+
+    /// A success carrying a remember-device ticket, as sent after a
+    /// two-factor code.
+    pub fn trusted_device(remember_device_ticket: &str) -> Self {
+        LoginReply::Success {
+            ticket: "mock-ticket".to_string(),
+            session_id: "mock-session".to_string(),
+            name_on_platform: "mock-user".to_string(),
+            remember_me_ticket: Some("mock-remember-me".to_string()),
+            remember_device_ticket: Some(remember_device_ticket.to_string()),
+            lifetime: 3 * 3600,
+        }
+    }
+
+    /// A success whose session expires `lifetime` seconds after it was
+    /// created.
+    pub fn expiring(ticket: &str, lifetime: u32) -> Self {
+        LoginReply::Success {
+            ticket: ticket.to_string(),
+            session_id: "mock-session".to_string(),
+            name_on_platform: "mock-user".to_string(),
+            remember_me_ticket: Some("mock-remember-me".to_string()),
+            remember_device_ticket: None,
+            lifetime,
+        }
+    }
+
+    fn render(&self) -> (u16, &'static str, Vec<(String, String)>, String) {
+        match self {
+            LoginReply::Success {
+                ticket,
+                session_id,
+                name_on_platform,
+                remember_me_ticket,
+                remember_device_ticket,
+                lifetime,
+            } => (
+                200,
+                "OK",
+                vec![("Date".to_string(), SERVER_DATE.to_string())],
+                serde_json::json!({
+                    "ticket": ticket,
+                    "sessionId": session_id,
+                    "userId": "00000000-0000-0000-0000-000000000001",
+                    "profileId": "00000000-0000-0000-0000-000000000001",
+                    "nameOnPlatform": name_on_platform,
+                    "rememberMeTicket": remember_me_ticket,
+                    "twoFactorAuthenticationTicket": null,
+                    "rememberDeviceTicket": remember_device_ticket,
+                    "serverTime": "2024-01-01T00:00:00.0000000Z",
+                    "expiration": format!(
+                        "2024-01-{:02}T{:02}:{:02}:{:02}.0000000Z",
+                        1 + lifetime / 86400,
+                        lifetime % 86400 / 3600,
+                        lifetime % 3600 / 60,
+                        lifetime % 60
+                    ),
+                })
+                .to_string(),
+            ),
+            LoginReply::BadCredentials => (
+                401,
+                "Unauthorized",
+                vec![],
+                serde_json::json!({
+                    "errorCode": 1,
+                    "httpCode": 401,
+                    "message": "Invalid credentials",
+                    "errorContext": "Profiles Client Facade",
+                })
+                .to_string(),
+            ),
+            LoginReply::TwoFactorRequired { ticket } => (
+                200,
+                "OK",
+                vec![],
+                serde_json::json!({
+                    "ticket": null,
+                    "twoFactorAuthenticationTicket": ticket,
+                    "codeGenerationPreference": ["app", "email"],
+                })
+                .to_string(),
+            ),
+            LoginReply::InvalidTwoFactorCode => (
+                400,
+                "Bad Request",
+                vec![],
+                serde_json::json!({
+                    "errorCode": 1101,
+                    "httpCode": 400,
+                    "message": "Invalid two-factor authentication code",
+                    "errorContext": "Profiles Client Facade",
+                })
+                .to_string(),
+            ),
+            LoginReply::RateLimited { retry_after } => (
+                429,
+                "Too Many Requests",
+                vec![("Retry-After".to_string(), retry_after.to_string())],
+                serde_json::json!({
+                    "errorCode": 3,
+                    "httpCode": 429,
+                    "message": "Too many calls",
+                    "errorContext": "Profiles Client Facade",
+                })
+                .to_string(),
+            ),
+            LoginReply::MalformedJson => (200, "OK", vec![], "{\"ticket\": ".to_string()),
+            LoginReply::Chunked(inner) => inner.render(),
+            LoginReply::SessionDeleted => (204, "No Content", vec![], String::new()),
+        }
+    }
+
+    fn write_to<W: Write>(&self, stream: &mut W) -> std::io::Result<()> {
+        let (status, reason, headers, body) = self.render();
+
+        let mut head = format!(
+            "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nConnection: close\r\n",
+            status, reason
+        );
+        for (name, value) in headers {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+
+        if let LoginReply::Chunked(_) = self {
+            head.push_str("Transfer-Encoding: chunked\r\n\r\n");
+            stream.write_all(head.as_bytes())?;
+            for chunk in body.as_bytes().chunks(16) {
+                write!(stream, "{:x}\r\n", chunk.len())?;
+                stream.write_all(chunk)?;
+                stream.write_all(b"\r\n")?;
+            }
+            stream.write_all(b"0\r\n\r\n")?;
+        } else {
+            head.push_str(&format!("Content-Length: {}\r\n\r\n", body.len()));
+            stream.write_all(head.as_bytes())?;
+            stream.write_all(body.as_bytes())?;
+        }
+
+        stream.flush()
+    }
+}
+
+/// A request received by the mock session endpoint.
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub path: String,
+    /// Header names are lower-cased.
+    pub headers: HashMap<String, String>,
+    pub body: String,
+}
+
+struct State {
+    replies: VecDeque<LoginReply>,
+    fallback: LoginReply,
+    requests: Vec<RecordedRequest>,
+}
+
+/// A plain-HTTP stand-in for `public-ubiservices.ubi.com/v3/profiles/sessions`.
+///
+/// Scripted replies are served in order; once they run out every request gets
+/// the fallback reply.
+pub struct MockLoginServer {
+    addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+}
+
+impl MockLoginServer {
+    pub fn start(fallback: LoginReply, addr: &str) -> Result<Self, Box<dyn Error>> {
+        let listener = TcpListener::bind(addr)?;
+        let addr = listener.local_addr()?;
+        let state = Arc::new(Mutex::new(State {
+            replies: VecDeque::new(),
+            fallback,
+            requests: vec![],
+        }));
+
+        let server = state.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let server = server.clone();
+                thread::spawn(move || {
+                    if let Err(e) = handle(stream, &server) {
+                        log::warn!("Mock login connection failed: {}", e);
+                    }
+                });
+            }
+        });
+
+        log::info!("Mock login server listening on {}", addr);
+        Ok(Self { addr, state })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Endpoint that points `login_at` at this server.
+    pub fn endpoint(&self) -> LoginEndpoint {
+        LoginEndpoint {
+            host: self.addr.ip().to_string(),
+            port: self.addr.port(),
+            path: "/v3/profiles/sessions".to_string(),
+            tls: false,
+        }
+    }
+
+    /// Queues a reply for the next unanswered request.
+    pub fn push_reply(&self, reply: LoginReply) {
+        self.state.lock().unwrap().replies.push_back(reply);
+    }
+
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.state.lock().unwrap().requests.clone()
+    }
+}
+
+fn handle(stream: TcpStream, state: &Mutex<State>) -> Result<(), Box<dyn Error>> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let path = parts.next().unwrap_or_default().to_string();
+
+    let mut headers = HashMap::new();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.insert(name.trim().to_ascii_lowercase(), value.trim().to_string());
+        }
+    }
+
+    let length = headers
+        .get("content-length")
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0);
+    let mut body = vec![0u8; length];
+    reader.read_exact(&mut body)?;
+
+    let reply = {
+        let mut state = state.lock().unwrap();
+        state.requests.push(RecordedRequest {
+            method: method.clone(),
+            path,
+            headers,
+            body: String::from_utf8_lossy(&body).into_owned(),
+        });
+        if method == "DELETE" {
+            LoginReply::SessionDeleted
+        } else {
+            let fallback = state.fallback.clone();
+            state.replies.pop_front().unwrap_or(fallback)
+        }
+    };
+
+    let mut stream = stream;
+    reply.write_to(&mut stream)?;
+    Ok(())
+}
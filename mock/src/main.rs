@@ -0,0 +1,75 @@
+use std::{path::PathBuf, process::ExitCode};
+
+use dbdata_mock::{Fixture, LoginReply, MockDemuxServer, MockLoginServer};
+
+const USAGE: &str = "usage: dbdata-mock [--bind <addr>] [--login-bind <addr>] [fixture.json]";
+
+fn main() -> ExitCode {
+    let mut bind = "127.0.0.1:7777".to_string();
+    let mut login_bind: Option<String> = None;
+    let mut fixture_path: Option<PathBuf> = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--bind" => match args.next() {
+                Some(addr) => bind = addr,
+                None => {
+                    eprintln!("{}", USAGE);
+                    return ExitCode::FAILURE;
+                }
+            },
+            "--login-bind" => match args.next() {
+                Some(addr) => login_bind = Some(addr),
+                None => {
+                    eprintln!("{}", USAGE);
+                    return ExitCode::FAILURE;
+                }
+            },
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return ExitCode::SUCCESS;
+            }
+            path => fixture_path = Some(PathBuf::from(path)),
+        }
+    }
+
+    let fixture = match fixture_path {
+        Some(path) => match Fixture::load(&path) {
+            Ok(fixture) => fixture,
+            Err(e) => {
+                eprintln!("{}", e);
+                return ExitCode::FAILURE;
+            }
+        },
+        None => Fixture::default(),
+    };
+
+    let server = match MockDemuxServer::start(fixture, &bind) {
+        Ok(server) => server,
+        Err(e) => {
+            eprintln!("Failed to start mock demux server: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    println!("Mock demux server listening on {}", server.addr());
+
+    let _login = match login_bind {
+        Some(addr) => match MockLoginServer::start(LoginReply::success(), &addr) {
+            Ok(login) => {
+                println!("Mock login server listening on {}", login.addr());
+                Some(login)
+            }
+            Err(e) => {
+                eprintln!("Failed to start mock login server: {}", e);
+                return ExitCode::FAILURE;
+            }
+        },
+        None => None,
+    };
+
+    loop {
+        std::thread::park();
+    }
+}
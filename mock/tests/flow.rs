@@ -0,0 +1,542 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, UNIX_EPOCH};
+
+use dbdata_core::auth::transport::{ConnectOptions, connect_tcp};
+use dbdata_core::auth::{
+    AuthResult, DemuxSocket, LoginCredentials, ProgressEvent, ProgressSink, REFRESH_MARGIN, Stage,
+    TokenClient, TwoFactorChallenge, authenticate_and_get_tokens, fetch_tokens,
+};
+use dbdata_core::config::{DbDataConfig, NetworkConfig};
+use dbdata_core::error::DbDataError;
+use dbdata_core::proto::demux::connection_closed_push::ConnectionErrorCode;
+use dbdata_core::time;
+use dbdata_core::token::Token;
+use dbdata_mock::login::SERVER_DATE;
+use dbdata_mock::{Fixture, LoginReply, MockDemuxServer, MockLoginServer};
+
+const APP_ID: u32 = 4553;
+
+fn fixture(faults: &str) -> Fixture {
+    Fixture::from_json(&format!(
+        r#"{{
+            "ticket": "mock-ticket",
+            "games": [
+                {{ "product_id": 4553, "associations": [4554, 4555] }},
+                {{ "product_id": 4554 }},
+                {{ "product_id": 4555, "owned": false }}
+            ],
+            "faults": {}
+        }}"#,
+        faults
+    ))
+    .unwrap()
+}
+
+fn run(server: &MockDemuxServer, ticket: &str) -> Result<AuthResult, DbDataError> {
+    let addr = server.addr();
+    let stream = connect_tcp(
+        &addr.ip().to_string(),
+        addr.port(),
+        &ConnectOptions::default(),
+    )
+    .unwrap();
+    let socket = DemuxSocket::from_transport(stream).unwrap();
+
+    let credentials = LoginCredentials {
+        ticket: ticket.to_string(),
+        session_id: "mock-session".to_string(),
+        ..Default::default()
+    };
+    let result = fetch_tokens(&socket, &credentials, APP_ID, "request-token", vec![]);
+    socket.disconnect();
+    result
+}
+
+#[test]
+fn fetches_tokens_offline() {
+    let server = MockDemuxServer::start(fixture("[]"), "127.0.0.1:0").unwrap();
+
+    let result = run(&server, "mock-ticket").unwrap();
+    assert_eq!(result.game_token, "mock-game-token");
+    assert_eq!(
+        result.ownership_token.as_deref(),
+        Some("mock-ownership-list-token")
+    );
+    assert_eq!(result.owned_dlcs, vec![4554]);
+
+    let stats = server.stats();
+    assert_eq!(stats.client_versions, vec![11200]);
+    assert_eq!(
+        stats.opened_services,
+        vec!["ownership_service", "denuvo_service"]
+    );
+}
+
+fn config(demux: &MockDemuxServer, login: &MockLoginServer) -> DbDataConfig {
+    DbDataConfig {
+        app_id: APP_ID,
+        email: "user@example.com".to_string(),
+        password: "hunter2".to_string(),
+        remember_me_ticket: None,
+        remember_device_ticket: None,
+        network: NetworkConfig {
+            demux_host: demux.addr().ip().to_string(),
+            demux_port: demux.addr().port(),
+            demux_tls: false,
+            login_host: login.addr().ip().to_string(),
+            login_port: login.addr().port(),
+            login_tls: false,
+            ..NetworkConfig::default()
+        },
+    }
+}
+
+fn no_prompt(_: &TwoFactorChallenge) -> Option<String> {
+    None
+}
+
+fn methods(login: &MockLoginServer) -> Vec<String> {
+    login.requests().into_iter().map(|r| r.method).collect()
+}
+
+#[test]
+fn full_flow_runs_against_local_stand_ins() {
+    let demux = MockDemuxServer::start(fixture("[]"), "127.0.0.1:0").unwrap();
+    let login = MockLoginServer::start(LoginReply::success(), "127.0.0.1:0").unwrap();
+
+    let config = config(&demux, &login);
+    let result = authenticate_and_get_tokens(&config, &no_prompt, "request-token", vec![]).unwrap();
+    assert_eq!(result.game_token, "mock-game-token");
+    assert_eq!(demux.stats().authenticated_tickets, vec!["mock-ticket"]);
+
+    assert_eq!(methods(&login), vec!["POST", "DELETE"]);
+    let logout = &login.requests()[1];
+    assert_eq!(logout.headers["authorization"], "Ubi_v1 t=mock-ticket");
+    assert_eq!(logout.headers["ubi-sessionid"], "mock-session");
+}
+
+#[test]
+fn tokens_carry_issue_and_expiry_times_by_the_server_clock() {
+    let mut fixture = fixture("[]");
+    fixture.ownership_token_expiration = 1_893_456_000;
+    let demux = MockDemuxServer::start(fixture, "127.0.0.1:0").unwrap();
+    let login = MockLoginServer::start(LoginReply::success(), "127.0.0.1:0").unwrap();
+
+    let result =
+        authenticate_and_get_tokens(&config(&demux, &login), &no_prompt, "request-token", vec![])
+            .unwrap();
+
+    // The mock's clock is stuck at its `Date` header.
+    let server_now = time::parse_http_date(SERVER_DATE).unwrap();
+    assert!(result.clock_skew < 0);
+    let issued = result.issued_at.duration_since(server_now).unwrap();
+    assert!(issued < Duration::from_secs(60), "{:?}", issued);
+    assert_eq!(
+        result.expires_at,
+        Some(UNIX_EPOCH + Duration::from_secs(1_893_456_000))
+    );
+
+    let token = Token::from_values(result.game_token, None).with_lifetime(
+        result.issued_at,
+        result.expires_at,
+        result.clock_skew,
+    );
+    assert!(!token.expires_within(REFRESH_MARGIN));
+}
+
+#[test]
+fn out_of_range_expirations_count_as_no_expiry() {
+    let mut fixture = fixture("[]");
+    fixture.ownership_token_expiration = u64::MAX;
+    let server = MockDemuxServer::start(fixture, "127.0.0.1:0").unwrap();
+
+    let result = run(&server, "mock-ticket").unwrap();
+    assert_eq!(result.expires_at, None);
+}
+
+#[test]
+fn expiring_session_is_refreshed_before_fetching() {
+    let demux = MockDemuxServer::start(fixture("[]"), "127.0.0.1:0").unwrap();
+    let login = MockLoginServer::start(LoginReply::success(), "127.0.0.1:0").unwrap();
+    login.push_reply(LoginReply::expiring("mock-ticket", 60));
+
+    let client = TokenClient::connect(&config(&demux, &login), &no_prompt).unwrap();
+    for _ in 0..2 {
+        client.fetch("request-token", vec![]).unwrap();
+    }
+    assert!(client.credentials().expires_at.is_some());
+    client.close();
+    drop(client);
+
+    assert_eq!(methods(&login), vec!["POST", "PUT", "DELETE"]);
+    let refresh = &login.requests()[1];
+    assert_eq!(refresh.headers["authorization"], "Ubi_v1 t=mock-ticket");
+    assert_eq!(refresh.headers["ubi-sessionid"], "mock-session");
+}
+
+#[test]
+fn one_client_fetches_tokens_for_several_apps() {
+    let demux = MockDemuxServer::start(fixture("[]"), "127.0.0.1:0").unwrap();
+    let login = MockLoginServer::start(LoginReply::success(), "127.0.0.1:0").unwrap();
+
+    let client = TokenClient::connect(&config(&demux, &login), &no_prompt).unwrap();
+    client.fetch_app(4554, "request-token", vec![]).unwrap();
+    let err = client.fetch_app(9999, "request-token", vec![]).unwrap_err();
+    assert!(
+        matches!(err, DbDataError::NotOwned(Some(9999))),
+        "{:?}",
+        err
+    );
+    client.fetch("request-token", vec![]).unwrap();
+    drop(client);
+
+    assert_eq!(methods(&login), vec!["POST", "DELETE"]);
+}
+
+type Events = Arc<Mutex<Vec<(Stage, &'static str)>>>;
+
+/// Records every progress event as the stage and how it ended.
+fn recorder() -> (Events, Arc<dyn ProgressSink>) {
+    let events = Events::default();
+    let sink = events.clone();
+    let record = move |event: &ProgressEvent| {
+        let outcome = match event {
+            ProgressEvent::Started(_) => "started",
+            ProgressEvent::Finished { .. } => "finished",
+            ProgressEvent::Failed { .. } => "failed",
+        };
+        sink.lock().unwrap().push((event.stage(), outcome));
+    };
+    (events, Arc::new(record))
+}
+
+#[test]
+fn every_stage_reports_its_progress() {
+    let demux = MockDemuxServer::start(fixture("[]"), "127.0.0.1:0").unwrap();
+    let login = MockLoginServer::start(LoginReply::success(), "127.0.0.1:0").unwrap();
+    let (events, sink) = recorder();
+
+    let client =
+        TokenClient::connect_with_progress(&config(&demux, &login), &no_prompt, sink).unwrap();
+    client.fetch("request-token", vec![]).unwrap();
+
+    let expected: Vec<_> = [
+        Stage::Login,
+        Stage::DemuxConnect,
+        Stage::OwnershipInitialize,
+        Stage::OwnershipToken,
+        Stage::DenuvoToken,
+        Stage::OwnershipListToken,
+    ]
+    .into_iter()
+    .flat_map(|stage| [(stage, "started"), (stage, "finished")])
+    .collect();
+    assert_eq!(*events.lock().unwrap(), expected);
+}
+
+#[test]
+fn failed_denuvo_request_is_retried_without_logging_in_again() {
+    let demux = MockDemuxServer::start(
+        fixture(r#"[{ "on": "game_token", "action": "denuvo_result", "result": "TimeOut" }]"#),
+        "127.0.0.1:0",
+    )
+    .unwrap();
+    let login = MockLoginServer::start(LoginReply::success(), "127.0.0.1:0").unwrap();
+    let (events, sink) = recorder();
+
+    let client =
+        TokenClient::connect_with_progress(&config(&demux, &login), &no_prompt, sink).unwrap();
+    let mut flow = client.flow(APP_ID, "request-token", vec![]).unwrap();
+    let err = flow.run().unwrap_err();
+    assert!(err.is_retryable(), "{:?}", err);
+    assert_eq!(flow.stage(), Some(Stage::DenuvoToken));
+    assert_eq!(
+        events.lock().unwrap().last(),
+        Some(&(Stage::DenuvoToken, "failed"))
+    );
+
+    events.lock().unwrap().clear();
+    let result = flow.run().unwrap();
+    assert_eq!(result.game_token, "mock-game-token");
+    assert_eq!(flow.stage(), None);
+    assert_eq!(events.lock().unwrap()[0], (Stage::DenuvoToken, "started"));
+    drop(flow);
+    drop(client);
+
+    assert_eq!(methods(&login), vec!["POST", "DELETE"]);
+    assert_eq!(
+        demux.stats().opened_services,
+        vec!["ownership_service", "denuvo_service"]
+    );
+}
+
+#[test]
+fn failed_refresh_keeps_a_ticket_that_has_not_expired() {
+    let demux = MockDemuxServer::start(fixture("[]"), "127.0.0.1:0").unwrap();
+    let login = MockLoginServer::start(LoginReply::success(), "127.0.0.1:0").unwrap();
+    login.push_reply(LoginReply::expiring("mock-ticket", 60));
+    login.push_reply(LoginReply::BadCredentials);
+
+    let client = TokenClient::connect(&config(&demux, &login), &no_prompt).unwrap();
+    let result = client.fetch("request-token", vec![]).unwrap();
+    assert_eq!(result.game_token, "mock-game-token");
+    drop(client);
+
+    assert_eq!(methods(&login), vec!["POST", "PUT", "DELETE"]);
+}
+
+#[test]
+fn saved_remember_me_ticket_replaces_the_password() {
+    let demux = MockDemuxServer::start(fixture("[]"), "127.0.0.1:0").unwrap();
+    let login = MockLoginServer::start(LoginReply::success(), "127.0.0.1:0").unwrap();
+
+    let config = DbDataConfig {
+        password: String::new(),
+        remember_me_ticket: Some("saved-ticket".to_string()),
+        ..config(&demux, &login)
+    };
+    let client = TokenClient::connect(&config, &no_prompt).unwrap();
+    assert_eq!(
+        client.remember_me_ticket().as_deref(),
+        Some("mock-remember-me")
+    );
+    client.close();
+
+    let requests = login.requests();
+    assert_eq!(methods(&login), vec!["POST", "DELETE"]);
+    assert_eq!(
+        requests[0].headers.get("authorization").unwrap(),
+        "rm_v1 t=saved-ticket"
+    );
+}
+
+#[test]
+fn rejected_remember_me_ticket_falls_back_to_the_password() {
+    let demux = MockDemuxServer::start(fixture("[]"), "127.0.0.1:0").unwrap();
+    let login = MockLoginServer::start(LoginReply::success(), "127.0.0.1:0").unwrap();
+    login.push_reply(LoginReply::BadCredentials);
+
+    let config = DbDataConfig {
+        remember_me_ticket: Some("stale-ticket".to_string()),
+        ..config(&demux, &login)
+    };
+    authenticate_and_get_tokens(&config, &no_prompt, "request-token", vec![]).unwrap();
+
+    let requests = login.requests();
+    assert_eq!(methods(&login), vec!["POST", "POST", "DELETE"]);
+    assert!(requests[1].headers["authorization"].starts_with("Basic "));
+}
+
+#[test]
+fn rejected_remember_me_ticket_without_password_fails() {
+    let demux = MockDemuxServer::start(fixture("[]"), "127.0.0.1:0").unwrap();
+    let login = MockLoginServer::start(LoginReply::BadCredentials, "127.0.0.1:0").unwrap();
+
+    let config = DbDataConfig {
+        password: String::new(),
+        remember_me_ticket: Some("stale-ticket".to_string()),
+        ..config(&demux, &login)
+    };
+    let err =
+        authenticate_and_get_tokens(&config, &no_prompt, "request-token", vec![]).unwrap_err();
+    assert!(matches!(err, DbDataError::RememberMeExpired), "{:?}", err);
+    assert_eq!(login.requests().len(), 1);
+    assert_eq!(demux.stats().connections, 0);
+}
+
+#[test]
+fn answers_injected_keep_alives() {
+    let server = MockDemuxServer::start(
+        fixture(r#"[{ "on": "game_token", "action": "keep_alive", "times": 2 }]"#),
+        "127.0.0.1:0",
+    )
+    .unwrap();
+
+    run(&server, "mock-ticket").unwrap();
+    assert_eq!(server.stats().keep_alives, 1);
+}
+
+#[test]
+fn wrong_ticket_is_rejected() {
+    let server = MockDemuxServer::start(fixture("[]"), "127.0.0.1:0").unwrap();
+
+    let err = run(&server, "other-ticket").unwrap_err();
+    assert!(
+        matches!(err, DbDataError::DemuxAuthRejected { .. }),
+        "{:?}",
+        err
+    );
+    assert_eq!(server.stats().authenticated_tickets, vec!["other-ticket"]);
+}
+
+#[test]
+fn connection_closed_fails_the_flow() {
+    for (name, code) in [
+        (
+            "Connection_ForceQuit",
+            ConnectionErrorCode::ConnectionForceQuit,
+        ),
+        (
+            "Connection_MultipleLogin",
+            ConnectionErrorCode::ConnectionMultipleLogin,
+        ),
+        ("Connection_Banned", ConnectionErrorCode::ConnectionBanned),
+        (
+            "Connection_NoAuthFromClient",
+            ConnectionErrorCode::ConnectionNoAuthFromClient,
+        ),
+    ] {
+        let faults = format!(
+            r#"[{{ "on": "initialize", "action": "connection_closed", "error_code": "{}" }}]"#,
+            name
+        );
+        let server = MockDemuxServer::start(fixture(&faults), "127.0.0.1:0").unwrap();
+        let err = run(&server, "mock-ticket").unwrap_err();
+        assert!(
+            matches!(err, DbDataError::ConnectionClosed(Some(c)) if c == code),
+            "{}: {:?}",
+            name,
+            err
+        );
+    }
+}
+
+#[test]
+fn client_outdated_pushes_the_version_again_and_retries_once() {
+    let server = MockDemuxServer::start(
+        fixture(r#"[{ "on": "ownership_token", "action": "client_outdated" }]"#),
+        "127.0.0.1:0",
+    )
+    .unwrap();
+
+    let result = run(&server, "mock-ticket").unwrap();
+    assert_eq!(result.game_token, "mock-game-token");
+    assert_eq!(server.stats().client_versions, vec![11200, 11200]);
+}
+
+#[test]
+fn client_outdated_during_authentication_is_retried_once() {
+    let server = MockDemuxServer::start(
+        fixture(r#"[{ "on": "authenticate", "action": "client_outdated" }]"#),
+        "127.0.0.1:0",
+    )
+    .unwrap();
+
+    let result = run(&server, "mock-ticket").unwrap();
+    assert_eq!(result.game_token, "mock-game-token");
+    let stats = server.stats();
+    assert_eq!(stats.client_versions, vec![11200, 11200]);
+    assert_eq!(stats.authenticated_tickets.len(), 2);
+}
+
+#[test]
+fn client_outdated_twice_fails_the_flow() {
+    let server = MockDemuxServer::start(
+        fixture(r#"[{ "on": "ownership_token", "action": "client_outdated", "times": 2 }]"#),
+        "127.0.0.1:0",
+    )
+    .unwrap();
+
+    let err = run(&server, "mock-ticket").unwrap_err();
+    assert!(matches!(err, DbDataError::ClientOutdated), "{:?}", err);
+}
+
+#[test]
+fn denuvo_failure_codes_fail_the_flow() {
+    for (result, retryable) in [
+        ("NotOwned", false),
+        ("Failure", false),
+        ("ExceededActivations", false),
+        ("TimeOut", true),
+        ("ServerError", true),
+        ("NoSessions", true),
+    ] {
+        let faults = format!(
+            r#"[{{ "on": "game_token", "action": "denuvo_result", "result": "{}" }}]"#,
+            result
+        );
+        let server = MockDemuxServer::start(fixture(&faults), "127.0.0.1:0").unwrap();
+        let err = run(&server, "mock-ticket").unwrap_err();
+        assert_eq!(err.is_retryable(), retryable, "{}: {:?}", result, err);
+    }
+}
+
+#[test]
+fn ownership_rejections_fail_with_a_typed_error() {
+    for (on, request) in [
+        ("initialize", "initialize"),
+        ("ownership_token", "ownership token"),
+    ] {
+        let faults = format!(r#"[{{ "on": "{}", "action": "reject" }}]"#, on);
+        let server = MockDemuxServer::start(fixture(&faults), "127.0.0.1:0").unwrap();
+        let err = run(&server, "mock-ticket").unwrap_err();
+        assert!(
+            matches!(&err, DbDataError::OwnershipRejected(r) if r == request),
+            "{}: {:?}",
+            on,
+            err
+        );
+    }
+}
+
+#[test]
+fn ownership_list_failure_is_not_fatal() {
+    let server = MockDemuxServer::start(
+        fixture(r#"[{ "on": "ownership_list_token", "action": "denuvo_result", "result": "ServerError" }]"#),
+        "127.0.0.1:0",
+    )
+    .unwrap();
+
+    let result = run(&server, "mock-ticket").unwrap();
+    assert_eq!(result.ownership_token, None);
+}
+
+#[test]
+fn time_trials_get_a_time_token_that_expires_with_its_ttl() {
+    let mut fixture =
+        fixture(r#"[{ "on": "game_token", "action": "denuvo_result", "result": "NotOwned" }]"#);
+    fixture.games[0].time_trial = true;
+    fixture.time_token_ttl_sec = 600;
+    let server = MockDemuxServer::start(fixture, "127.0.0.1:0").unwrap();
+
+    // The fault on the plain game token is never hit.
+    let result = run(&server, "mock-ticket").unwrap();
+    assert!(result.time_trial);
+    assert_eq!(result.game_token, "mock-time-token");
+    assert_eq!(
+        result.expires_at,
+        Some(result.issued_at + Duration::from_secs(600))
+    );
+
+    let token = Token::from_values(result.game_token, None).with_lifetime(
+        result.issued_at,
+        result.expires_at,
+        result.clock_skew,
+    );
+    assert_eq!(
+        token.refresh_margin(REFRESH_MARGIN),
+        Duration::from_secs(300)
+    );
+}
+
+#[test]
+fn time_token_failures_fail_the_flow() {
+    let mut fixture = fixture(
+        r#"[{ "on": "game_time_token", "action": "denuvo_result", "result": "ExceededActivations" }]"#,
+    );
+    fixture.games[0].time_trial = true;
+    let server = MockDemuxServer::start(fixture, "127.0.0.1:0").unwrap();
+
+    let err = run(&server, "mock-ticket").unwrap_err();
+    assert!(!err.is_retryable(), "{:?}", err);
+}
+
+#[test]
+fn unknown_fault_names_are_rejected() {
+    assert!(
+        Fixture::from_json(
+            r#"{ "faults": [{ "on": "game_token", "action": "denuvo_result", "result": "Nope" }] }"#
+        )
+        .is_err()
+    );
+}
@@ -0,0 +1,249 @@
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use dbdata_core::auth::transport::ConnectOptions;
+use std::cell::RefCell;
+
+use dbdata_core::auth::{LoginCredentials, TwoFactorChallenge, login_at, login_with_prompt};
+use dbdata_core::error::DbDataError;
+use dbdata_core::http::HttpClient;
+use dbdata_mock::{LoginReply, MockLoginServer};
+
+fn server(reply: LoginReply) -> MockLoginServer {
+    MockLoginServer::start(reply, "127.0.0.1:0").unwrap()
+}
+
+fn login(server: &MockLoginServer, password: &str) -> Result<LoginCredentials, DbDataError> {
+    login_at(
+        &HttpClient::new(ConnectOptions::default()),
+        &server.endpoint(),
+        "user@example.com",
+        password,
+    )
+}
+
+#[test]
+fn successful_login_returns_ticket_and_session() {
+    let server = server(LoginReply::success());
+
+    let credentials = login(&server, "hunter2").unwrap();
+    assert_eq!(credentials.ticket, "mock-ticket");
+    assert_eq!(credentials.session_id, "mock-session");
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 1);
+    let request = &requests[0];
+    assert_eq!(request.method, "POST");
+    assert_eq!(request.path, "/v3/profiles/sessions");
+    assert_eq!(
+        request.headers.get("authorization").unwrap(),
+        &format!("Basic {}", BASE64.encode("user@example.com:hunter2"))
+    );
+    assert_eq!(
+        request.headers.get("ubi-requestedplatformtype").unwrap(),
+        "uplay"
+    );
+    assert_eq!(request.body, r#"{"rememberMe":true}"#);
+}
+
+#[test]
+fn bad_credentials_fail() {
+    let server = server(LoginReply::BadCredentials);
+
+    let err = login(&server, "wrong").unwrap_err();
+    assert!(
+        matches!(
+            err,
+            DbDataError::Login {
+                status: 401,
+                error_code: Some(1),
+                ref message,
+            } if message == "Invalid credentials"
+        ),
+        "{:?}",
+        err
+    );
+    assert!(!err.is_retryable());
+}
+
+#[test]
+fn two_factor_requirement_is_reported() {
+    let server = server(LoginReply::TwoFactorRequired {
+        ticket: "2fa-ticket".to_string(),
+    });
+
+    let err = login(&server, "hunter2").unwrap_err();
+    assert!(
+        matches!(err, DbDataError::TwoFactorRequired { ref ticket } if ticket == "2fa-ticket"),
+        "{:?}",
+        err
+    );
+}
+
+fn two_factor_server() -> MockLoginServer {
+    let server = server(LoginReply::trusted_device("rd-ticket"));
+    server.push_reply(LoginReply::TwoFactorRequired {
+        ticket: "2fa-ticket".to_string(),
+    });
+    server
+}
+
+fn login_answering(
+    server: &MockLoginServer,
+    remember_device_ticket: Option<&str>,
+    codes: &[&str],
+) -> (
+    Result<LoginCredentials, DbDataError>,
+    Vec<TwoFactorChallenge>,
+) {
+    let challenges = RefCell::new(vec![]);
+    let prompt = |challenge: &TwoFactorChallenge| {
+        let mut challenges = challenges.borrow_mut();
+        challenges.push(challenge.clone());
+        codes.get(challenges.len() - 1).map(|code| code.to_string())
+    };
+
+    let result = login_with_prompt(
+        &HttpClient::new(ConnectOptions::default()),
+        &server.endpoint(),
+        "user@example.com",
+        "hunter2",
+        remember_device_ticket,
+        &prompt,
+    );
+    (result, challenges.into_inner())
+}
+
+#[test]
+fn two_factor_code_completes_the_login() {
+    let server = two_factor_server();
+
+    let (result, challenges) = login_answering(&server, None, &["123456"]);
+    let credentials = result.unwrap();
+    assert_eq!(credentials.ticket, "mock-ticket");
+    assert_eq!(
+        credentials.remember_device_ticket.as_deref(),
+        Some("rd-ticket")
+    );
+    assert_eq!(challenges.len(), 1);
+    assert_eq!(challenges[0].methods, vec!["app", "email"]);
+    assert_eq!(challenges[0].attempt, 1);
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(
+        requests[1].headers.get("authorization").unwrap(),
+        "ubi_2fa_v1 t=2fa-ticket"
+    );
+    assert_eq!(requests[1].headers.get("ubi-2facode").unwrap(), "123456");
+    let body: serde_json::Value = serde_json::from_str(&requests[1].body).unwrap();
+    assert_eq!(body["rememberMe"], true);
+    assert!(body["trustedDevice"]["id"].is_string());
+}
+
+#[test]
+fn remember_device_ticket_is_sent_with_the_password() {
+    let server = server(LoginReply::success());
+
+    let (result, challenges) = login_answering(&server, Some("rd-ticket"), &[]);
+    assert!(result.is_ok());
+    assert!(challenges.is_empty());
+
+    let requests = server.requests();
+    assert_eq!(
+        requests[0].headers.get("ubi-rememberdeviceticket").unwrap(),
+        "rd-ticket"
+    );
+    assert_eq!(requests[0].body, r#"{"rememberMe":true}"#);
+}
+
+#[test]
+fn rejected_two_factor_codes_are_asked_again() {
+    let server = two_factor_server();
+    server.push_reply(LoginReply::InvalidTwoFactorCode);
+
+    let (result, challenges) = login_answering(&server, None, &["000000", "123456"]);
+    assert!(result.is_ok());
+    assert_eq!(
+        challenges.iter().map(|c| c.attempt).collect::<Vec<_>>(),
+        vec![1, 2]
+    );
+    assert_eq!(server.requests().len(), 3);
+}
+
+#[test]
+fn two_factor_gives_up_after_three_rejected_codes() {
+    let server = server(LoginReply::InvalidTwoFactorCode);
+    server.push_reply(LoginReply::TwoFactorRequired {
+        ticket: "2fa-ticket".to_string(),
+    });
+
+    let (result, challenges) = login_answering(&server, None, &["1", "2", "3", "4"]);
+    let err = result.unwrap_err();
+    assert!(
+        matches!(err, DbDataError::TwoFactorCodeRejected),
+        "{:?}",
+        err
+    );
+    assert_eq!(challenges.len(), 3);
+    assert_eq!(server.requests().len(), 4);
+}
+
+#[test]
+fn cancelled_two_factor_prompt_fails() {
+    let server = two_factor_server();
+
+    let (result, challenges) = login_answering(&server, None, &[]);
+    let err = result.unwrap_err();
+    assert!(
+        matches!(err, DbDataError::TwoFactorRequired { .. }),
+        "{:?}",
+        err
+    );
+    assert_eq!(challenges.len(), 1);
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[test]
+fn rate_limiting_fails() {
+    let server = server(LoginReply::RateLimited { retry_after: 30 });
+
+    let err = login(&server, "hunter2").unwrap_err();
+    assert!(
+        matches!(
+            err,
+            DbDataError::Login {
+                status: 429,
+                error_code: Some(3),
+                ..
+            }
+        ),
+        "{:?}",
+        err
+    );
+    assert!(err.is_retryable());
+}
+
+#[test]
+fn chunked_bodies_are_decoded() {
+    let server = server(LoginReply::Chunked(Box::new(LoginReply::success())));
+
+    let credentials = login(&server, "hunter2").unwrap();
+    assert_eq!(credentials.ticket, "mock-ticket");
+}
+
+#[test]
+fn malformed_json_fails() {
+    let server = server(LoginReply::MalformedJson);
+
+    let err = login(&server, "hunter2").unwrap_err();
+    assert!(matches!(err, DbDataError::Protocol(_)), "{:?}", err);
+}
+
+#[test]
+fn scripted_replies_are_served_in_order() {
+    let server = server(LoginReply::success());
+    server.push_reply(LoginReply::BadCredentials);
+
+    assert!(login(&server, "hunter2").is_err());
+    assert!(login(&server, "hunter2").is_ok());
+    assert_eq!(server.requests().len(), 2);
+}
@@ -0,0 +1,233 @@
+use std::thread;
+use std::time::Duration;
+
+use dbdata_core::auth::transport::{ConnectOptions, connect_tcp};
+use dbdata_core::auth::{
+    Connector, DemuxSession, DemuxSocket, LoginCredentials, SessionOptions, request_tokens,
+};
+use dbdata_core::error::DbDataError;
+use dbdata_core::proto::demux::connection_closed_push::ConnectionErrorCode;
+use dbdata_mock::{Fixture, MockDemuxServer};
+
+const APP_ID: u32 = 4553;
+
+fn fixture(faults: &str) -> Fixture {
+    Fixture::from_json(&format!(
+        r#"{{
+            "ticket": "mock-ticket",
+            "games": [{{ "product_id": 4553 }}],
+            "faults": {}
+        }}"#,
+        faults
+    ))
+    .unwrap()
+}
+
+fn options() -> SessionOptions {
+    SessionOptions {
+        keep_alive_interval: Duration::from_secs(60),
+        initial_backoff: Duration::from_millis(10),
+        max_backoff: Duration::from_millis(40),
+        max_attempts: 3,
+    }
+}
+
+fn connector(server: &MockDemuxServer) -> Connector {
+    let addr = server.addr();
+    Box::new(move || {
+        let stream = connect_tcp(
+            &addr.ip().to_string(),
+            addr.port(),
+            &ConnectOptions::default(),
+        )?;
+        DemuxSocket::from_transport(stream)
+    })
+}
+
+fn start(server: &MockDemuxServer, options: SessionOptions) -> DemuxSession {
+    DemuxSession::start(connector(server), "mock-ticket", options).unwrap()
+}
+
+fn credentials() -> LoginCredentials {
+    LoginCredentials {
+        ticket: "mock-ticket".to_string(),
+        session_id: "mock-session".to_string(),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn serves_several_fetches_on_one_connection() {
+    let server = MockDemuxServer::start(fixture("[]"), "127.0.0.1:0").unwrap();
+    let session = start(&server, options());
+
+    for _ in 0..2 {
+        let result =
+            request_tokens(&session, &credentials(), APP_ID, "request-token", vec![]).unwrap();
+        assert_eq!(result.game_token, "mock-game-token");
+    }
+
+    let stats = server.stats();
+    assert_eq!(stats.connections, 1);
+    assert_eq!(stats.authenticated_tickets, vec!["mock-ticket"]);
+    assert_eq!(
+        stats.opened_services,
+        vec!["ownership_service", "denuvo_service"]
+    );
+}
+
+#[test]
+fn sends_keep_alives_on_a_timer() {
+    let server = MockDemuxServer::start(fixture("[]"), "127.0.0.1:0").unwrap();
+    let session = start(
+        &server,
+        SessionOptions {
+            keep_alive_interval: Duration::from_millis(20),
+            ..options()
+        },
+    );
+
+    thread::sleep(Duration::from_millis(150));
+    session.close();
+    assert!(server.stats().keep_alives >= 2);
+}
+
+#[test]
+fn reconnects_and_reopens_services_when_the_connection_drops() {
+    let server = MockDemuxServer::start(
+        fixture(r#"[{ "on": "game_token", "action": "disconnect" }]"#),
+        "127.0.0.1:0",
+    )
+    .unwrap();
+    let session = start(&server, options());
+
+    let result = request_tokens(&session, &credentials(), APP_ID, "request-token", vec![]).unwrap();
+    assert_eq!(result.game_token, "mock-game-token");
+
+    let stats = server.stats();
+    assert_eq!(stats.connections, 2);
+    assert_eq!(stats.client_versions, vec![11200, 11200]);
+    assert_eq!(
+        stats.authenticated_tickets,
+        vec!["mock-ticket", "mock-ticket"]
+    );
+    assert_eq!(
+        stats.opened_services,
+        vec![
+            "ownership_service",
+            "denuvo_service",
+            "ownership_service",
+            "denuvo_service"
+        ]
+    );
+}
+
+#[test]
+fn slow_replies_are_asked_again_without_reconnecting() {
+    let server = MockDemuxServer::start(
+        fixture(r#"[{ "on": "game_token", "action": "delay", "ms": 450 }]"#),
+        "127.0.0.1:0",
+    )
+    .unwrap();
+    let addr = server.addr();
+    let connector: Connector = Box::new(move || {
+        let stream = connect_tcp(
+            &addr.ip().to_string(),
+            addr.port(),
+            &ConnectOptions::default(),
+        )?;
+        Ok(DemuxSocket::from_transport(stream)?.with_timeout(Duration::from_millis(300)))
+    });
+    let session = DemuxSession::start(connector, "mock-ticket", options()).unwrap();
+
+    let result = request_tokens(&session, &credentials(), APP_ID, "request-token", vec![]).unwrap();
+    assert_eq!(result.game_token, "mock-game-token");
+
+    let stats = server.stats();
+    assert_eq!(stats.connections, 1);
+    assert_eq!(
+        stats.opened_services,
+        vec!["ownership_service", "denuvo_service"]
+    );
+}
+
+#[test]
+fn retries_the_first_connection_with_backoff() {
+    let server = MockDemuxServer::start(
+        fixture(r#"[{ "on": "authenticate", "action": "disconnect", "times": 2 }]"#),
+        "127.0.0.1:0",
+    )
+    .unwrap();
+    let _session = start(&server, options());
+
+    assert_eq!(server.stats().connections, 3);
+}
+
+#[test]
+fn gives_up_after_max_attempts() {
+    let server = MockDemuxServer::start(
+        fixture(r#"[{ "on": "authenticate", "action": "disconnect", "times": 5 }]"#),
+        "127.0.0.1:0",
+    )
+    .unwrap();
+
+    let result = DemuxSession::start(connector(&server), "mock-ticket", options());
+    assert!(result.is_err());
+    assert_eq!(server.stats().connections, 3);
+}
+
+#[test]
+fn rejected_tickets_are_not_retried() {
+    let server = MockDemuxServer::start(fixture("[]"), "127.0.0.1:0").unwrap();
+
+    let result = DemuxSession::start(connector(&server), "other-ticket", options());
+    assert!(result.is_err());
+    assert_eq!(server.stats().connections, 1);
+}
+
+#[test]
+fn reopens_a_service_connection_the_server_force_quit() {
+    let server = MockDemuxServer::start(
+        fixture(
+            r#"[{ "on": "game_token", "action": "connection_closed", "error_code": "Connection_ForceQuit" }]"#,
+        ),
+        "127.0.0.1:0",
+    )
+    .unwrap();
+    let session = start(&server, options());
+
+    let result = request_tokens(&session, &credentials(), APP_ID, "request-token", vec![]).unwrap();
+    assert_eq!(result.game_token, "mock-game-token");
+
+    let stats = server.stats();
+    assert_eq!(stats.connections, 1);
+    assert_eq!(
+        stats.opened_services,
+        vec!["ownership_service", "denuvo_service", "denuvo_service"]
+    );
+}
+
+#[test]
+fn multiple_login_is_reported_without_retrying() {
+    let server = MockDemuxServer::start(
+        fixture(
+            r#"[{ "on": "game_token", "action": "connection_closed", "error_code": "Connection_MultipleLogin" }]"#,
+        ),
+        "127.0.0.1:0",
+    )
+    .unwrap();
+    let session = start(&server, options());
+
+    let err =
+        request_tokens(&session, &credentials(), APP_ID, "request-token", vec![]).unwrap_err();
+    assert!(
+        matches!(
+            err,
+            DbDataError::ConnectionClosed(Some(ConnectionErrorCode::ConnectionMultipleLogin))
+        ),
+        "{:?}",
+        err
+    );
+    assert!(err.explanation().contains("other PC"));
+    assert_eq!(server.stats().opened_services.len(), 2);
+}
@@ -1,7 +0,0 @@
-mod login;
-mod demux;
-mod flow;
-
-pub use login::*;
-pub use demux::*;
-pub use flow::*;
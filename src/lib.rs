@@ -1,29 +1,57 @@
-mod auth;
-mod config;
+//! Windows DLL shim exposing `IGameTokenInterface` on top of `dbdata_core`.
+
+#![cfg(windows)]
+
 mod logging;
-mod proto;
-mod services;
-mod token;
+mod prompt;
 
 use std::{
+    collections::BTreeMap,
     ffi::{CString, OsString, c_void},
-    os::windows::ffi::OsStringExt,
+    os::windows::{ffi::OsStringExt, io::AsRawHandle},
     path::{Path, PathBuf},
-    sync::{OnceLock, RwLock},
+    sync::{Arc, Condvar, Mutex, MutexGuard, OnceLock, RwLock, mpsc},
+    time::{Duration, SystemTime},
 };
 
 use winapi::{
     shared::minwindef::{DWORD, HINSTANCE, LPVOID},
-    um::{libloaderapi::GetModuleFileNameW, winnt::DLL_PROCESS_ATTACH, winuser::MessageBoxA},
+    um::{
+        libloaderapi::GetModuleFileNameW,
+        winnt::{DLL_PROCESS_ATTACH, DLL_PROCESS_DETACH},
+        winuser::MessageBoxA,
+    },
 };
 
-use crate::config::DbDataConfig;
-use crate::token::{Settings, Token};
+use dbdata_core::auth;
+use dbdata_core::buffers::Buffers;
+use dbdata_core::config::DbDataConfig;
+use dbdata_core::error::DbDataError;
+use dbdata_core::remember_device::RememberDeviceStore;
+use dbdata_core::token::{self, Settings, Token};
+
+type Worker = auth::TokenWorker<()>;
+
+/// How long `get_cached_or_fresh_token` waits for a token when it has none
+/// to hand out. The fetch carries on in the background after that.
+const FETCH_WAIT: Duration = Duration::from_secs(30);
 
 static DLL_PATH: OnceLock<PathBuf> = OnceLock::new();
-static APP_ID: OnceLock<u32> = OnceLock::new();
-static SETTINGS: RwLock<Option<Settings>> = RwLock::new(None);
+/// Cached tokens and DLCs by app id.
+static SETTINGS: RwLock<BTreeMap<u32, Settings>> = RwLock::new(BTreeMap::new());
 static DBDATA_CONFIG: OnceLock<Option<DbDataConfig>> = OnceLock::new();
+/// Online token fetches, by app id. Finished ones stay until the next fetch
+/// for the same app, so the thread handles from [`get_thread`] stay valid
+/// until then.
+static WORKERS: Mutex<BTreeMap<u32, Arc<Worker>>> = Mutex::new(BTreeMap::new());
+/// The request token the game last passed to `get_cached_or_fresh_token`,
+/// by app id, for fetches started from `new_thread_get_burn_ticket_res`.
+static REQUEST_TOKENS: Mutex<BTreeMap<u32, String>> = Mutex::new(BTreeMap::new());
+/// The pending time trial refresh of each app. A new token replaces it.
+static REFRESH_TIMERS: Mutex<BTreeMap<u32, Arc<RefreshTimer>>> = Mutex::new(BTreeMap::new());
+/// Login and demux session, kept open between fetches only while a time
+/// trial refresh is pending.
+static TOKEN_CLIENT: Mutex<Option<auth::TokenClient>> = Mutex::new(None);
 
 #[unsafe(no_mangle)]
 extern "system" fn DllMain(module: HINSTANCE, reason: DWORD, _reserved: LPVOID) -> bool {
@@ -39,38 +67,19 @@ extern "system" fn DllMain(module: HINSTANCE, reason: DWORD, _reserved: LPVOID)
                 let path = Path::new(&path);
                 path.parent().unwrap().to_path_buf()
             };
-            DLL_PATH.set(dll_path.clone()).ok();
+            DLL_PATH.set(dll_path).ok();
 
             logging::init_logger();
             logging::setup_panic_handler();
-
-            if !DbDataConfig::exists(&dll_path) {
-                if let Err(e) = DbDataConfig::create_default(&dll_path) {
-                    log::error!("Failed to create default dbdata.ini: {}", e);
-                } else {
-                    message_box(
-                        "Setup Required",
-                        "A default 'dbdata.ini' has been created.\n\nPlease edit it with your Ubisoft account credentials (email and password) and restart the game.",
-                    );
-                    std::process::exit(0);
-                }
-            }
-
-            if let Ok(settings) = Settings::new(&dll_path) {
-                if let Ok(mut s) = SETTINGS.write() {
-                    *s = Some(settings);
-                }
-                log::info!("Loaded existing token from dbdata.ini");
-            } else {
-                log::info!("No valid token in dbdata.ini - will try online auth");
+        }
+        DLL_PROCESS_DETACH => {
+            // Nothing may block or touch the network under the loader lock.
+            // Fetches log out when they finish, so only a session kept for
+            // a time trial refresh, or one still in use, is left here to
+            // expire on the server.
+            if let Ok(mut client) = TOKEN_CLIENT.try_lock() {
+                std::mem::forget(client.take());
             }
-
-            let dbdata_config = DbDataConfig::load(&dll_path)
-                .map_err(|e| {
-                    log::info!("Could not read dbdata.ini config: {}", e);
-                })
-                .ok();
-            DBDATA_CONFIG.set(dbdata_config).ok();
         }
         _ => {}
     }
@@ -78,11 +87,36 @@ extern "system" fn DllMain(module: HINSTANCE, reason: DWORD, _reserved: LPVOID)
     true
 }
 
+/// Interfaces handed to the game, one per app id. They are never freed, as
+/// the game keeps using them until it exits.
+static INTERFACES: Mutex<BTreeMap<u32, &'static IGameTokenInterface>> = Mutex::new(BTreeMap::new());
+
 #[repr(C)]
 pub struct IGameTokenInterface {
     vtable: *const IGameTokenInterfaceVtable,
+    /// Only read by us; the game only looks at the vtable.
+    app_id: u32,
+    buffers: Mutex<Buffers>,
 }
 
+// The vtable pointer always points at the immutable `VTABLE`, and the rest
+// is behind a mutex.
+unsafe impl Send for IGameTokenInterface {}
+unsafe impl Sync for IGameTokenInterface {}
+
+static VTABLE: IGameTokenInterfaceVtable = IGameTokenInterfaceVtable {
+    is_token_loaded: is_token_loaded as *const c_void,
+    return_0: return_0 as *const c_void,
+    get_cached_or_fresh_token: get_cached_or_fresh_token as *const c_void,
+    invalidate_cached_token: invalidate_cached_token as *const c_void,
+    get_buffer: get_buffer as *const c_void,
+    new_thread_get_burn_ticket_res: new_thread_get_burn_ticket_res as *const c_void,
+    get_thread: get_thread as *const c_void,
+    get_ownership_buffer: get_ownership_buffer as *const c_void,
+    get_dlcs: get_dlcs as *const c_void,
+    set_arg_to_0: set_arg_to_0 as *const c_void,
+};
+
 #[repr(C, align(32))]
 pub struct IGameTokenInterfaceVtable {
     is_token_loaded: *const c_void,
@@ -97,35 +131,110 @@ pub struct IGameTokenInterfaceVtable {
     set_arg_to_0: *const c_void,
 }
 
+// Only function pointers, never written after compilation.
+unsafe impl Sync for IGameTokenInterfaceVtable {}
+
 #[unsafe(export_name = "?getGameTokenInterface@@YAPEAVIGameTokenInterface@@PEAX_K@Z")]
 pub extern "C" fn get_game_token_interface(
     app_id: *const i64,
     version: i64,
 ) -> *const IGameTokenInterface {
-    let app_id = unsafe { *app_id };
-
-    APP_ID.set(app_id as u32).ok();
+    let app_id = unsafe { *app_id } as u32;
 
     log::info!("getGameTokenInterface called {:?} {:?}", app_id, version);
 
-    let vtable = Box::new(IGameTokenInterfaceVtable {
-        is_token_loaded: is_token_loaded as *const c_void,
-        return_0: return_0 as *const c_void,
-        get_cached_or_fresh_token: get_cached_or_fresh_token as *const c_void,
-        invalidate_cached_token: invalidate_cached_token as *const c_void,
-        get_buffer: get_buffer as *const c_void,
-        new_thread_get_burn_ticket_res: new_thread_get_burn_ticket_res as *const c_void,
-        get_thread: get_thread as *const c_void,
-        get_ownership_buffer: get_ownership_buffer as *const c_void,
-        get_dlcs: get_dlcs as *const c_void,
-        set_arg_to_0: set_arg_to_0 as *const c_void,
-    });
+    dbdata_config();
+    load_settings(app_id);
 
-    let example = Box::new(IGameTokenInterface {
-        vtable: Box::into_raw(vtable),
+    let mut interfaces = INTERFACES.lock().unwrap();
+    let interface = interfaces.entry(app_id).or_insert_with(|| {
+        Box::leak(Box::new(IGameTokenInterface {
+            vtable: &VTABLE,
+            app_id,
+            buffers: Mutex::new(Buffers::default()),
+        }))
     });
 
-    Box::into_raw(example)
+    *interface as *const IGameTokenInterface
+}
+
+/// The settings from `dbdata.ini`, read on first use rather than in
+/// `DllMain`, since decrypting them must not run under the loader lock. On
+/// the first run this writes a default file and exits.
+fn dbdata_config() -> Option<&'static DbDataConfig> {
+    DBDATA_CONFIG
+        .get_or_init(|| {
+            let dll_path = DLL_PATH.get().unwrap();
+            if !DbDataConfig::exists(dll_path) {
+                if let Err(e) = DbDataConfig::create_default(dll_path) {
+                    log::error!("Failed to create default dbdata.ini: {}", e);
+                } else {
+                    message_box(
+                        "Setup Required",
+                        "A default 'dbdata.ini' has been created.\n\nPlease edit it with your Ubisoft account credentials (email and password) and restart the game.",
+                    );
+                    std::process::exit(0);
+                }
+            }
+
+            DbDataConfig::load(dll_path)
+                .map_err(|e| {
+                    log::info!("Could not read dbdata.ini config: {}", e);
+                })
+                .ok()
+        })
+        .as_ref()
+}
+
+/// Loads the cached token for `app_id` from `dbdata.ini`, unless an earlier
+/// interface already did.
+fn load_settings(app_id: u32) {
+    let Ok(mut settings) = SETTINGS.write() else {
+        return;
+    };
+    if settings.contains_key(&app_id) {
+        return;
+    }
+
+    match Settings::new(DLL_PATH.get().unwrap(), app_id) {
+        Ok(loaded) => {
+            settings.insert(app_id, loaded);
+            log::info!("Loaded existing token for app {} from dbdata.ini", app_id);
+        }
+        Err(e) => log::info!(
+            "No valid token for app {} in dbdata.ini - will try online auth: {}",
+            app_id,
+            e
+        ),
+    }
+}
+
+/// File name of the game executable, part of the token fingerprint.
+fn executable_name() -> String {
+    std::env::current_exe()
+        .ok()
+        .and_then(|path| {
+            path.file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+        })
+        .unwrap_or_default()
+}
+
+/// The app id the game passed to `getGameTokenInterface` for `this`.
+fn app_id(this: *const IGameTokenInterface) -> u32 {
+    unsafe { (*this).app_id }
+}
+
+fn buffers(this: *const IGameTokenInterface) -> MutexGuard<'static, Buffers> {
+    let interface: &'static IGameTokenInterface = unsafe { &*this };
+    interface
+        .buffers
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn cached_settings<T>(app_id: u32, f: impl FnOnce(&Settings) -> T) -> Option<T> {
+    SETTINGS.read().ok()?.get(&app_id).map(f)
 }
 
 fn is_token_loaded(this: *const IGameTokenInterface) -> u32 {
@@ -152,67 +261,81 @@ fn get_cached_or_fresh_token(
         length
     );
 
-    let has_settings = SETTINGS.read().map(|s| s.is_some()).unwrap_or(false);
-    if has_settings {
-        log::info!("Using existing token from dbdata.ini");
-        return true;
-    }
-
     let request_token = unsafe {
         std::ffi::CStr::from_ptr(token_buffer_ptr as *const i8)
             .to_str()
             .unwrap()
     };
+    let app_id = app_id(this);
     let dll_path = DLL_PATH.get().unwrap();
-    let app_id = *APP_ID.get().unwrap();
-
-    if let Some(Some(dbdata_config)) = DBDATA_CONFIG.get() {
-        log::info!("has_credentials={}", dbdata_config.has_credentials());
-        if dbdata_config.has_credentials() {
-            log::info!("Attempting online authentication with Ubisoft");
-
-            let mut config = dbdata_config.clone();
-            config.app_id = app_id;
-
-            match auth::authenticate_and_get_tokens(&config, request_token, vec![]) {
-                Ok(result) => {
-                    log::info!("Authentication successful, saving tokens");
+    REQUEST_TOKENS
+        .lock()
+        .unwrap()
+        .insert(app_id, request_token.to_string());
 
-                    let token = Token::from_values(
-                        result.game_token.clone(),
-                        result.ownership_token.clone(),
-                    );
-
-                    if let Err(e) = token.save_with_dlcs(dll_path, &result.owned_dlcs) {
-                        log::error!("Failed to save tokens: {}", e);
-                    }
+    let (cached, needs_fetch) = cached_token(app_id, request_token);
+    if !needs_fetch && cached.is_some() {
+        log::info!("Using existing token from dbdata.ini");
+        return true;
+    }
 
-                    if let Ok(mut settings) = SETTINGS.write() {
-                        *settings = Some(Settings {
-                            dlcs: result.owned_dlcs,
-                            token,
-                        });
-                    }
+    if needs_fetch {
+        let result = start_worker(app_id, request_token).and_then(|worker| {
+            // A cached token that still works is handed out while the new one
+            // is fetched.
+            if cached
+                .as_ref()
+                .is_some_and(|token| !token.expires_within(Duration::ZERO))
+            {
+                log::info!("Cached token expires soon, refreshing it in the background");
+                return Ok(true);
+            }
 
-                    log::info!("Online authentication complete, game can continue");
-                    return true;
-                }
-                Err(e) => {
-                    log::error!("Authentication failed: {}", e);
-                    message_box(
-                        "Authentication Failed",
-                        &format!(
-                            "Failed to authenticate: {}\n\nFalling back to token_req.txt",
-                            e
-                        ),
-                    );
+            match worker.wait(Some(FETCH_WAIT)) {
+                auth::WorkerStatus::Done => Ok(true),
+                auth::WorkerStatus::Running => Ok(false),
+                auth::WorkerStatus::Failed => {
+                    Err(worker.take().and_then(Result::err).unwrap_or_else(|| {
+                        DbDataError::Protocol("Token fetch ended without a result".to_string())
+                    }))
                 }
             }
-        } else {
-            log::info!("No credentials in dbdata.ini, falling back to token_req.txt");
+        });
+
+        match result {
+            Ok(true) => {
+                log::info!("Token ready, game can continue");
+                return true;
+            }
+            Ok(false) => {
+                log::warn!(
+                    "No token after {:?}, the fetch goes on in the background",
+                    FETCH_WAIT
+                );
+                return false;
+            }
+            Err(e)
+                if cached
+                    .as_ref()
+                    .is_some_and(|token| !token.expires_within(Duration::ZERO)) =>
+            {
+                log::warn!("Token refresh failed, using the cached token: {}", e);
+                return true;
+            }
+            Err(e) => {
+                log::error!("Authentication failed: {}", e);
+                message_box(
+                    "Authentication Failed",
+                    &format!(
+                        "{}\n\n({})\n\nFalling back to token_req.txt",
+                        e.explanation(),
+                        e
+                    ),
+                );
+            }
         }
     } else {
-        log::info!("DBDATA_CONFIG is None, falling back to token_req.txt");
+        log::info!("No credentials in dbdata.ini, falling back to token_req.txt");
     }
 
     let request_token = format!("{}|{}", request_token, app_id);
@@ -226,36 +349,283 @@ fn get_cached_or_fresh_token(
     std::process::exit(0);
 }
 
+/// The credentials to log in with, if `dbdata.ini` has any.
+fn login_config(app_id: u32) -> Option<DbDataConfig> {
+    let Some(config) = dbdata_config() else {
+        return None;
+    };
+    if !config.has_credentials() {
+        return None;
+    }
+
+    let mut config = config.clone();
+    config.app_id = app_id;
+    Some(config)
+}
+
+/// The cached token for `app_id`, unless it was issued for another build or
+/// request token, and whether a new one should be fetched online.
+fn cached_token(app_id: u32, request_token: &str) -> (Option<Token>, bool) {
+    let fingerprint = token::fingerprint(app_id, &executable_name(), request_token);
+    let cached = cached_settings(app_id, |s| s.token.clone()).filter(|token| {
+        let matches = token.matches_fingerprint(&fingerprint);
+        if !matches {
+            log::info!("Cached token was issued for another game build or request token");
+        }
+        matches
+    });
+
+    let needs_fetch = login_config(app_id).is_some()
+        && cached
+            .as_ref()
+            .is_none_or(|token| token.expires_within(token.refresh_margin(auth::REFRESH_MARGIN)));
+    (cached, needs_fetch)
+}
+
+/// Starts fetching tokens for `app_id` in the background, or returns the
+/// fetch that is already running.
+fn start_worker(app_id: u32, request_token: &str) -> Result<Arc<Worker>, DbDataError> {
+    let mut workers = WORKERS.lock().unwrap();
+    if let Some(worker) = workers.get(&app_id)
+        && worker.status() == auth::WorkerStatus::Running
+    {
+        return Ok(worker.clone());
+    }
+
+    let config = login_config(app_id).ok_or(DbDataError::MissingCredentials)?;
+    let request_token = request_token.to_string();
+    // A time trial refresh has to wait for this fetch to finish, so the
+    // fetch is handed its own worker.
+    let (this_tx, this_rx) = mpsc::channel::<Arc<Worker>>();
+    let worker = Arc::new(auth::TokenWorker::spawn("dbdata-token", move || {
+        let fetched = fetch_online(config, &request_token);
+        match &fetched {
+            Ok(Some(token)) => {
+                if let Ok(fetch) = this_rx.recv() {
+                    schedule_refresh(app_id, &request_token, token, fetch);
+                }
+            }
+            Ok(None) => cancel_refresh(app_id),
+            Err(_) => {}
+        }
+        close_idle_client();
+        fetched.map(|_| ())
+    })?);
+    let _ = this_tx.send(worker.clone());
+    workers.insert(app_id, worker.clone());
+
+    Ok(worker)
+}
+
+/// Logs in if needed, fetches tokens for `config.app_id` and caches them in
+/// memory and in `dbdata.ini`. Returns the token if it is a time trial's,
+/// which has to be refreshed before it runs out.
+fn fetch_online(config: DbDataConfig, request_token: &str) -> Result<Option<Token>, DbDataError> {
+    log::info!("Attempting online authentication with Ubisoft");
+    let dll_path = DLL_PATH.get().unwrap();
+    let app_id = config.app_id;
+
+    let result = {
+        let mut client = TOKEN_CLIENT.lock().unwrap();
+        let connected = match client.take() {
+            Some(connected) => connected,
+            None => auth::TokenClient::connect(&config, &prompt::DialogPrompt)
+                .inspect(|connected| save_login_tickets(dll_path, &config, connected))?,
+        };
+        let result = connected.fetch_app(app_id, request_token, vec![]);
+        *client = Some(connected);
+        result?
+    };
+    log::info!("Authentication successful, saving tokens");
+
+    let token = Token::from_values(result.game_token, result.ownership_token)
+        .with_lifetime(result.issued_at, result.expires_at, result.clock_skew)
+        .with_fingerprint(token::fingerprint(
+            app_id,
+            &executable_name(),
+            request_token,
+        ));
+
+    if let Err(e) = token.save_with_dlcs(dll_path, app_id, &result.owned_dlcs) {
+        log::error!("Failed to save tokens: {}", e);
+    }
+
+    let refresh = result.time_trial.then(|| token.clone());
+    if let Ok(mut settings) = SETTINGS.write() {
+        settings.insert(
+            app_id,
+            Settings {
+                dlcs: result.owned_dlcs,
+                token,
+            },
+        );
+    }
+
+    Ok(refresh)
+}
+
+/// Logs out unless a time trial refresh still needs the session. Runs at
+/// the end of each fetch, as `DllMain` must not touch the network.
+fn close_idle_client() {
+    if !REFRESH_TIMERS.lock().unwrap().is_empty() {
+        return;
+    }
+    let client = TOKEN_CLIENT.lock().unwrap().take();
+    if let Some(client) = client {
+        log::info!("No refresh pending, logging out");
+        client.close();
+    }
+}
+
+/// A pending refresh, woken early when it is cancelled.
+#[derive(Default)]
+struct RefreshTimer {
+    cancelled: Mutex<bool>,
+    wake: Condvar,
+}
+
+impl RefreshTimer {
+    fn cancel(&self) {
+        *self.cancelled.lock().unwrap() = true;
+        self.wake.notify_all();
+    }
+
+    /// Waits for `delay` to pass. Returns false if the timer was cancelled
+    /// first.
+    fn sleep(&self, delay: Duration) -> bool {
+        let cancelled = self.cancelled.lock().unwrap();
+        let (cancelled, _) = self
+            .wake
+            .wait_timeout_while(cancelled, delay, |cancelled| !*cancelled)
+            .unwrap();
+        !*cancelled
+    }
+}
+
+/// Fetches the time token of a trial again shortly before it runs out, so
+/// the game keeps a valid one for as long as it runs. Replaces the refresh
+/// scheduled for an earlier token of the app. The timer only starts once
+/// `fetch`, the fetch that got the token, has finished.
+fn schedule_refresh(app_id: u32, request_token: &str, token: &Token, fetch: Arc<Worker>) {
+    let Some(refresh_at) = token.refresh_at(auth::REFRESH_MARGIN) else {
+        cancel_refresh(app_id);
+        return;
+    };
+    let delay = refresh_at
+        .duration_since(SystemTime::now())
+        .unwrap_or_default();
+    log::info!(
+        "Refreshing the time trial token for app {} in {:?}",
+        app_id,
+        delay
+    );
+
+    let timer = Arc::new(RefreshTimer::default());
+    if let Some(previous) = REFRESH_TIMERS.lock().unwrap().insert(app_id, timer.clone()) {
+        previous.cancel();
+    }
+
+    let request_token = request_token.to_string();
+    let spawned = std::thread::Builder::new()
+        .name("dbdata-trial-refresh".to_string())
+        .spawn(move || {
+            // Until `fetch` has finished, `start_worker` would hand it back
+            // instead of starting the refresh.
+            fetch.wait(None);
+            let delay = refresh_at
+                .duration_since(SystemTime::now())
+                .unwrap_or_default();
+            if !timer.sleep(delay) {
+                return;
+            }
+
+            {
+                let mut timers = REFRESH_TIMERS.lock().unwrap();
+                if !timers
+                    .get(&app_id)
+                    .is_some_and(|current| Arc::ptr_eq(current, &timer))
+                {
+                    return;
+                }
+                timers.remove(&app_id);
+            }
+            if let Err(e) = start_worker(app_id, &request_token) {
+                log::error!("Failed to refresh the time trial token: {}", e);
+            }
+        });
+    if let Err(e) = spawned {
+        log::error!("Failed to schedule the time trial refresh: {}", e);
+    }
+}
+
+/// Stops the pending time trial refresh of `app_id`, if there is one.
+fn cancel_refresh(app_id: u32) {
+    if let Some(timer) = REFRESH_TIMERS.lock().unwrap().remove(&app_id) {
+        timer.cancel();
+    }
+}
+
+/// Saves what lets the next launch log in without the password or a
+/// two-factor code.
+fn save_login_tickets(dll_path: &Path, config: &DbDataConfig, client: &auth::TokenClient) {
+    if let Some(ticket) = client.remember_me_ticket()
+        && let Err(e) = DbDataConfig::save_remember_me_ticket(dll_path, &ticket)
+    {
+        log::error!("Failed to save remember-me ticket: {}", e);
+    }
+
+    if let Some(ticket) = client.remember_device_ticket()
+        && !config.email.is_empty()
+        && let Err(e) = RememberDeviceStore::new(dll_path).save(
+            &config.email,
+            client.credentials().user_id.as_deref(),
+            &ticket,
+        )
+    {
+        log::error!("Failed to save remember-device ticket: {}", e);
+    }
+}
+
+/// Called when the game rejects the token. Forgets it in memory and in
+/// `dbdata.ini`, so the next `get_cached_or_fresh_token` fetches a new one.
 fn invalidate_cached_token(this: *const IGameTokenInterface) {
     log::info!("invalidate_cached_token called {:?}", this);
 
-    message_box(
-        "Info",
-        "Denuvo tried to delete the token, if this error persists your token might have become invalid",
-    );
+    let app_id = app_id(this);
+    cancel_refresh(app_id);
+    if let Ok(mut settings) = SETTINGS.write() {
+        settings.remove(&app_id);
+    }
+    if let Err(e) = Token::invalidate(DLL_PATH.get().unwrap(), app_id) {
+        log::error!("Failed to remove the cached token: {}", e);
+    }
 }
 
 fn get_buffer(this: *const IGameTokenInterface, length: *mut u64) -> *const c_void {
     log::info!("get_buffer called {:?} {:?}", this, length);
 
-    let token = SETTINGS
-        .read()
-        .ok()
-        .and_then(|s| s.as_ref().map(|s| s.token.token.clone()))
-        .unwrap_or_else(|| {
-            message_box("Error", "Token not found");
-            "".to_string()
-        });
+    let token = cached_settings(app_id(this), |s| s.token.token.clone()).unwrap_or_else(|| {
+        message_box("Error", "Token not found");
+        "".to_string()
+    });
 
+    let (buffer, len) = buffers(this).token(&token);
     if !length.is_null() {
         unsafe {
-            *length = token.len() as u64;
+            *length = len as u64;
         }
     }
 
-    Box::into_raw(token.into_boxed_str()) as *const c_void
+    buffer as *const c_void
 }
 
+/// Starts fetching a token in the background with the request token from
+/// the last `get_cached_or_fresh_token` call, or picks up the fetch that is
+/// already running.
+///
+/// Returns the handle of the fetch thread, which is signalled once it has
+/// finished, or null when nothing needs fetching or there are no
+/// credentials. `param` is left alone.
 fn new_thread_get_burn_ticket_res(this: *const IGameTokenInterface, param: i64) -> *const c_void {
     log::info!(
         "new_thread_get_burn_ticket_res called {:?} {:?}",
@@ -263,49 +633,65 @@ fn new_thread_get_burn_ticket_res(this: *const IGameTokenInterface, param: i64)
         param
     );
 
-    std::ptr::null()
+    let app_id = app_id(this);
+    let Some(request_token) = REQUEST_TOKENS.lock().unwrap().get(&app_id).cloned() else {
+        log::info!("No request token yet, not starting a background fetch");
+        return std::ptr::null();
+    };
+    if !cached_token(app_id, &request_token).1 {
+        return std::ptr::null();
+    }
+
+    match start_worker(app_id, &request_token) {
+        Ok(worker) => worker.as_raw_handle() as *const c_void,
+        Err(e) => {
+            log::error!("Failed to start the background token fetch: {}", e);
+            std::ptr::null()
+        }
+    }
 }
 
+/// Returns the handle of the last fetch thread for this app, signalled once
+/// it has finished, or null when none was started. `param` is left alone.
 fn get_thread(this: *const IGameTokenInterface, param: *mut u64) -> *const c_void {
     log::info!("get_thread called {:?} {:?}", this, param);
 
-    std::ptr::null()
+    match WORKERS.lock().unwrap().get(&app_id(this)) {
+        Some(worker) => worker.as_raw_handle() as *const c_void,
+        None => std::ptr::null(),
+    }
 }
 
 fn get_ownership_buffer(this: *const IGameTokenInterface, length: *mut u64) -> *const c_void {
     log::info!("get_ownership_buffer called {:?} {:?}", this, length);
 
-    let token = SETTINGS
-        .read()
-        .ok()
-        .and_then(|s| s.as_ref().and_then(|t| t.token.ownership.clone()))
+    let token = cached_settings(app_id(this), |s| s.token.ownership.clone())
+        .flatten()
         .unwrap_or_else(|| "".to_string());
 
+    let (buffer, len) = buffers(this).ownership(&token);
     if !length.is_null() {
         unsafe {
-            *length = token.len() as u64;
+            *length = len as u64;
         }
     }
 
-    Box::into_raw(token.to_string().into_boxed_str()) as *const c_void
+    buffer as *const c_void
 }
 
 fn get_dlcs(this: *const IGameTokenInterface, arg: *mut i64) -> *const i32 {
     log::info!("get_dlcs called {:?} {:?}", this, arg);
 
-    let dlcs = SETTINGS
-        .read()
-        .ok()
-        .and_then(|s| s.as_ref().map(|s| s.dlcs.clone()))
-        .unwrap_or_else(|| vec![]);
+    let dlcs = cached_settings(app_id(this), |s| s.dlcs.clone()).unwrap_or_else(|| vec![]);
 
+    let (buffer, len) = buffers(this).dlcs(&dlcs);
     if !arg.is_null() {
         unsafe {
-            *arg = dlcs.len() as i64;
+            *arg = len as i64;
         }
     }
 
-    Box::into_raw(dlcs.into_boxed_slice()) as *const i32
+    buffer
 }
 
 fn set_arg_to_0(this: *const IGameTokenInterface, arg: *mut u64) {
@@ -0,0 +1,175 @@
+use std::ptr;
+
+use winapi::{
+    shared::{
+        basetsd::INT_PTR,
+        minwindef::{LOWORD, LPARAM, UINT, WPARAM},
+        windef::HWND,
+    },
+    um::{
+        libloaderapi::GetModuleHandleW,
+        winuser::{
+            BS_DEFPUSHBUTTON, DS_CENTER, DS_MODALFRAME, DS_SETFONT, DialogBoxIndirectParamW,
+            ES_AUTOHSCROLL, EndDialog, GWLP_USERDATA, GetDlgItemTextW, GetWindowLongPtrW, IDCANCEL,
+            IDOK, SS_LEFT, SetWindowLongPtrW, WM_COMMAND, WM_INITDIALOG, WS_BORDER, WS_CAPTION,
+            WS_CHILD, WS_POPUP, WS_SYSMENU, WS_TABSTOP, WS_VISIBLE,
+        },
+    },
+};
+
+use dbdata_core::auth::{TwoFactorChallenge, TwoFactorPrompt};
+
+const ID_CODE: u16 = 100;
+const BUTTON: u16 = 0x0080;
+const EDIT: u16 = 0x0081;
+const STATIC: u16 = 0x0082;
+
+/// Asks for the two-factor code in a small modal dialog.
+pub struct DialogPrompt;
+
+impl TwoFactorPrompt for DialogPrompt {
+    fn code(&self, challenge: &TwoFactorChallenge) -> Option<String> {
+        let template = dialog_template(&challenge.message());
+        let mut code: Option<String> = None;
+
+        let result = unsafe {
+            DialogBoxIndirectParamW(
+                GetModuleHandleW(ptr::null()),
+                template.as_ptr() as *const _,
+                ptr::null_mut(),
+                Some(dialog_proc),
+                &mut code as *mut Option<String> as LPARAM,
+            )
+        };
+        if result == -1 {
+            log::error!("Failed to show the two-factor dialog");
+            return None;
+        }
+
+        code.filter(|code| !code.is_empty())
+    }
+}
+
+unsafe extern "system" fn dialog_proc(
+    hwnd: HWND,
+    msg: UINT,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> INT_PTR {
+    match msg {
+        WM_INITDIALOG => {
+            unsafe { SetWindowLongPtrW(hwnd, GWLP_USERDATA, lparam) };
+            1
+        }
+        WM_COMMAND => match LOWORD(wparam as u32) as i32 {
+            IDOK => {
+                let mut buffer = [0u16; 64];
+                let len = unsafe {
+                    GetDlgItemTextW(
+                        hwnd,
+                        ID_CODE as i32,
+                        buffer.as_mut_ptr(),
+                        buffer.len() as i32,
+                    )
+                };
+                let code = String::from_utf16_lossy(&buffer[..len as usize]);
+
+                let target =
+                    unsafe { GetWindowLongPtrW(hwnd, GWLP_USERDATA) } as *mut Option<String>;
+                if !target.is_null() {
+                    unsafe { *target = Some(code.trim().to_string()) };
+                }
+                unsafe { EndDialog(hwnd, IDOK as INT_PTR) };
+                1
+            }
+            IDCANCEL => {
+                unsafe { EndDialog(hwnd, IDCANCEL as INT_PTR) };
+                1
+            }
+            _ => 0,
+        },
+        _ => 0,
+    }
+}
+
+/// Builds an in-memory `DLGTEMPLATE` with the message, a code field and
+/// OK/Cancel buttons. Returned as `u32`s so the template is DWORD aligned.
+fn dialog_template(message: &str) -> Vec<u32> {
+    let mut words: Vec<u16> = vec![];
+
+    push_dword(
+        &mut words,
+        WS_POPUP | WS_CAPTION | WS_SYSMENU | DS_MODALFRAME | DS_CENTER | DS_SETFONT,
+    );
+    push_dword(&mut words, 0);
+    words.push(4);
+    words.extend([0, 0, 200, 72]);
+    words.push(0);
+    words.push(0);
+    push_string(&mut words, "Two-Factor Authentication");
+    words.push(9);
+    push_string(&mut words, "Segoe UI");
+
+    push_item(
+        &mut words,
+        SS_LEFT,
+        [7, 7, 186, 20],
+        0xFFFF,
+        STATIC,
+        message,
+    );
+    push_item(
+        &mut words,
+        WS_BORDER | WS_TABSTOP | ES_AUTOHSCROLL,
+        [7, 30, 186, 14],
+        ID_CODE,
+        EDIT,
+        "",
+    );
+    push_item(
+        &mut words,
+        WS_TABSTOP | BS_DEFPUSHBUTTON,
+        [89, 51, 50, 14],
+        IDOK as u16,
+        BUTTON,
+        "OK",
+    );
+    push_item(
+        &mut words,
+        WS_TABSTOP,
+        [143, 51, 50, 14],
+        IDCANCEL as u16,
+        BUTTON,
+        "Cancel",
+    );
+
+    if words.len() % 2 == 1 {
+        words.push(0);
+    }
+    words
+        .chunks(2)
+        .map(|pair| pair[0] as u32 | (pair[1] as u32) << 16)
+        .collect()
+}
+
+fn push_item(words: &mut Vec<u16>, style: u32, rect: [i16; 4], id: u16, class: u16, text: &str) {
+    if words.len() % 2 == 1 {
+        words.push(0);
+    }
+    push_dword(words, WS_CHILD | WS_VISIBLE | style);
+    push_dword(words, 0);
+    words.extend(rect.map(|v| v as u16));
+    words.push(id);
+    words.extend([0xFFFF, class]);
+    push_string(words, text);
+    words.push(0);
+}
+
+fn push_dword(words: &mut Vec<u16>, value: u32) {
+    words.extend([value as u16, (value >> 16) as u16]);
+}
+
+fn push_string(words: &mut Vec<u16>, value: &str) {
+    words.extend(value.encode_utf16());
+    words.push(0);
+}